@@ -0,0 +1,96 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Startup notification for launched apps.
+//!
+//! This service has no connection to the display, so Gio can't create startup notification IDs
+//! for launched apps on its own.  Without a startup ID Gnome Shell considers new windows
+//! unrequested, and may open them behind other windows.  We create startup IDs ourselves and
+//! pass them to apps in `$DESKTOP_STARTUP_ID`, which focuses their windows on X11.
+//!
+//! This doesn't help on Wayland: Gnome Shell only honours activation tokens which it issued
+//! itself through the xdg-activation protocol, which requires a connection to the display.  We
+//! never pass our startup IDs as `$XDG_ACTIVATION_TOKEN`, and remove tokens from our own
+//! environment, which would be stale anyway.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+/// The environment variable which carries the startup ID to launched apps.
+const STARTUP_ID_VARIABLE: &str = "DESKTOP_STARTUP_ID";
+
+/// The environment variable which carries Wayland activation tokens to launched apps.
+const ACTIVATION_TOKEN_VARIABLE: &str = "XDG_ACTIVATION_TOKEN";
+
+/// The sequence number of the next startup ID.
+static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// Create a startup ID for the app with `app_id` and the given `sequence` number.
+///
+/// Follow the format of Gio, i.e. `program-pid-app-sequence_TIMEtimestamp`, and replace
+/// whitespace in `app_id` which startup IDs must not contain.  Gnome Shell takes the `timestamp`
+/// of the user event which caused the launch from the startup ID, to tell launches by the user
+/// from focus stealing.  Omit the timestamp if it's 0, i.e. unknown.
+fn startup_id(app_id: &str, pid: u32, sequence: u32, timestamp: u32) -> String {
+    let app_id: String = app_id
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let id = format!("{}-{}-{}-{}", env!("CARGO_BIN_NAME"), pid, app_id, sequence);
+    if timestamp == 0 {
+        id
+    } else {
+        format!("{}_TIME{}", id, timestamp)
+    }
+}
+
+/// Create a new startup ID for the app with `app_id`, launched at `timestamp`.
+pub fn next_startup_id(app_id: &str, timestamp: u32) -> String {
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    startup_id(app_id, std::process::id(), sequence, timestamp)
+}
+
+/// Pass the startup `id` to all apps launched with `context`.
+///
+/// Don't pass any activation token; see the module documentation.
+pub fn set_startup_id(context: &gio::AppLaunchContext, id: &str) {
+    context.setenv(STARTUP_ID_VARIABLE, id);
+    context.unsetenv(ACTIVATION_TOKEN_VARIABLE);
+}
+
+/// Stop passing a startup ID to apps launched with `context`.
+pub fn unset_startup_id(context: &gio::AppLaunchContext) {
+    context.unsetenv(STARTUP_ID_VARIABLE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_ids() {
+        assert_eq!(
+            startup_id("code-oss.desktop", 42, 7, 0),
+            concat!(env!("CARGO_BIN_NAME"), "-42-code-oss.desktop-7")
+        );
+        assert_eq!(
+            startup_id("My Fork.desktop", 1, 0, 0),
+            concat!(env!("CARGO_BIN_NAME"), "-1-My_Fork.desktop-0")
+        );
+        assert_eq!(
+            startup_id("codium.desktop", 42, 3, 123456),
+            concat!(env!("CARGO_BIN_NAME"), "-42-codium.desktop-3_TIME123456")
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Installed apps.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use log::{debug, trace};
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+/// Look up installed apps by desktop ID.
+///
+/// Remember apps we looked up, including apps which aren't installed, until installed apps change.
+/// Requires a running Glib main loop to notice changes.
+#[derive(Debug, Clone)]
+pub struct AppRegistry {
+    /// Apps looked up so far, by desktop ID; `None` if the app isn't installed.
+    apps: Rc<RefCell<HashMap<String, Option<gio::DesktopAppInfo>>>>,
+    /// Tells us about changes to installed apps.
+    monitor: gio::AppInfoMonitor,
+}
+
+impl AppRegistry {
+    /// Create a new registry, and watch installed apps for changes.
+    pub fn new() -> Self {
+        let apps: Rc<RefCell<HashMap<String, Option<gio::DesktopAppInfo>>>> = Rc::default();
+        let monitor = gio::AppInfoMonitor::get();
+        let cached = Rc::downgrade(&apps);
+        monitor.connect_changed(move |_| {
+            if let Some(apps) = cached.upgrade() {
+                debug!("Installed apps changed, forgetting all apps looked up");
+                apps.borrow_mut().clear();
+            }
+        });
+        Self { apps, monitor }
+    }
+
+    /// Call `callback` whenever installed apps change.
+    ///
+    /// Lookups in `callback` already see the changed apps.
+    pub fn connect_changed<F: Fn() + 'static>(&self, callback: F) {
+        self.monitor.connect_changed(move |_| callback());
+    }
+
+    /// Look up the app with the given `desktop_id`.
+    ///
+    /// Return `None` if no such app is installed.
+    pub fn lookup(&self, desktop_id: &str) -> Option<gio::DesktopAppInfo> {
+        if let Some(app) = self.apps.borrow().get(desktop_id) {
+            return app.clone();
+        }
+        trace!("Looking up app {}", desktop_id);
+        let app = gio::DesktopAppInfo::new(desktop_id);
+        self.apps
+            .borrow_mut()
+            .insert(desktop_id.to_string(), app.clone());
+        app
+    }
+}
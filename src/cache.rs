@@ -0,0 +1,139 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A persistent cache of recent workspaces.
+//!
+//! Reading the storage of VSCode takes a while, which makes the first search after login slow.
+//! We keep the workspaces we found last in a cache file, along with stamps of the storage they
+//! came from, and take workspaces from the cache file if storage didn't change since.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::provider::WorkspaceItem;
+use crate::storage::FileStamp;
+
+/// The directory for caches of this service, i.e. `$XDG_CACHE_HOME/gnome-search-providers-vscode`.
+///
+/// `home_dir` is the home directory of the current user, to fall back to `~/.cache` if
+/// `$XDG_CACHE_HOME` is unset.
+pub fn cache_dir(home_dir: &Path) -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home_dir.join(".cache"))
+        .join(env!("CARGO_PKG_NAME"))
+}
+
+/// Workspaces found in storage.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CachedWorkspaces {
+    /// The version of this service which wrote the cache.
+    ///
+    /// We ignore caches of other versions, which may have a different format.
+    version: String,
+    /// Stamps of the storage the workspaces came from.
+    pub stamps: Vec<(PathBuf, FileStamp)>,
+    /// The workspaces, by ID, in order of recency.
+    pub items: Vec<(Arc<str>, WorkspaceItem)>,
+}
+
+impl CachedWorkspaces {
+    /// Cache `items` found in storage with `stamps`.
+    pub fn new(stamps: Vec<(PathBuf, FileStamp)>, items: Vec<(Arc<str>, WorkspaceItem)>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            stamps,
+            items,
+        }
+    }
+
+    /// Read cached workspaces from the file at `path`.
+    ///
+    /// Return `None` if the file doesn't exist, or if another version of this service wrote it.
+    pub fn from_file(path: &Path) -> Result<Option<Self>> {
+        trace!("Reading cached workspaces from {}", path.display());
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let cached: Self = serde_json::from_str(&contents).with_context(|| {
+                    format!("Failed to parse cached workspaces {}", path.display())
+                })?;
+                Ok(Some(cached).filter(|cached| cached.version == env!("CARGO_PKG_VERSION")))
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => {
+                Err(error).with_context(|| format!("Failed to open {} for reading", path.display()))
+            }
+        }
+    }
+
+    /// Write cached workspaces to the file at `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        let contents = serde_json::to_string(self)?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write cached workspaces to {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::WorkspaceTarget;
+    use crate::storage::RecentKind;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn save_and_read_cached_workspaces() {
+        let item = WorkspaceItem {
+            name: "mdcat".to_string(),
+            url: "file:///home/foo/mdcat".into(),
+            target: WorkspaceTarget::Uri("file:///home/foo/mdcat".into()),
+            path: Some("/home/foo/mdcat".to_string()),
+            segments: vec!["mdcat".to_string()],
+            suffix: None,
+            recency: 0,
+            kind: RecentKind::Folder,
+            remote: false,
+            git_remote: None,
+            description: Some("~".to_string()),
+            last_opened: Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+            profile: None,
+            workspace_file: None,
+            canonical_path: Some(PathBuf::from("/home/foo/mdcat")),
+            on_network_mount: false,
+        };
+        let cached = CachedWorkspaces::new(
+            vec![(
+                PathBuf::from("/home/foo/.config/Code/storage.json"),
+                Some((UNIX_EPOCH + Duration::from_secs(1_600_000_000), 42)),
+            )],
+            vec![(
+                "vscode-search-provider-code.desktop-file:///home/foo/mdcat".into(),
+                item,
+            )],
+        );
+        let path = std::env::temp_dir()
+            .join(format!(
+                "{}-cache-{}",
+                env!("CARGO_PKG_NAME"),
+                std::process::id()
+            ))
+            .join("code.desktop.json");
+        cached.save(&path).unwrap();
+        assert_eq!(CachedWorkspaces::from_file(&path).unwrap(), Some(cached));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(CachedWorkspaces::from_file(&path).unwrap(), None);
+    }
+}
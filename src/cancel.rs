@@ -0,0 +1,122 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Cancel searches which Gnome Shell superseded with a newer search.
+//!
+//! Gnome Shell sends a search for every keystroke, and drops the answer to a search once it sends
+//! a newer one.  We record the serial number of every search message as it arrives, and cancel a
+//! running search once a message with a higher serial arrives for the same provider.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Generations of searches of a single provider.
+///
+/// Generations are the serial numbers of search messages, which increase with every message.
+///
+/// Clones share generations.
+#[derive(Debug, Clone, Default)]
+pub struct SearchGeneration {
+    /// The generation of the latest search received for the provider.
+    latest: Arc<AtomicU64>,
+    /// The generation of the search the provider currently runs.
+    current: Arc<AtomicU64>,
+}
+
+impl SearchGeneration {
+    /// A token to cancel the current search once a newer search arrives.
+    pub fn token(&self) -> CancellationToken {
+        CancellationToken {
+            latest: self.latest.clone(),
+            generation: self.current.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Tells a search whether a newer search superseded it.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    /// The generation of the latest search received for the provider.
+    latest: Arc<AtomicU64>,
+    /// The generation of the search this token belongs to.
+    generation: u64,
+}
+
+impl CancellationToken {
+    /// Whether a newer search superseded the search of this token.
+    pub fn is_cancelled(&self) -> bool {
+        self.generation < self.latest.load(Ordering::SeqCst)
+    }
+}
+
+/// Generations of searches of all providers, by object path.
+///
+/// Clones share generations, to record searches on another thread while providers search.
+#[derive(Debug, Clone, Default)]
+pub struct Searches(Arc<Mutex<HashMap<String, SearchGeneration>>>);
+
+impl Searches {
+    /// The generations of searches of the provider at `path`.
+    pub fn generation(&self, path: &str) -> SearchGeneration {
+        match self.0.lock() {
+            Ok(mut generations) => generations.entry(path.to_string()).or_default().clone(),
+            // A thread panicked while recording a search; nothing gets cancelled anymore
+            Err(_) => SearchGeneration::default(),
+        }
+    }
+
+    /// Record that a search with the given `serial` arrived for the provider at `path`.
+    pub fn received(&self, path: &str, serial: u32) {
+        self.generation(path)
+            .latest
+            .fetch_max(u64::from(serial), Ordering::SeqCst);
+    }
+
+    /// Record that the provider at `path` starts the search with the given `serial`.
+    pub fn started(&self, path: &str, serial: u32) {
+        let generation = self.generation(path);
+        generation
+            .current
+            .store(u64::from(serial), Ordering::SeqCst);
+        generation
+            .latest
+            .fetch_max(u64::from(serial), Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "/de/swsnr/searchprovider/vscode/code";
+    const CODIUM: &str = "/de/swsnr/searchprovider/vscode/codium";
+
+    #[test]
+    fn cancel_superseded_searches() {
+        let searches = Searches::default();
+        let generation = searches.generation(CODE);
+        searches.received(CODE, 3);
+        searches.started(CODE, 3);
+        let token = generation.token();
+        assert!(!token.is_cancelled());
+        searches.received(CODIUM, 4);
+        assert!(!token.is_cancelled());
+        searches.received(CODE, 5);
+        assert!(token.is_cancelled());
+        searches.started(CODE, 5);
+        assert!(!generation.token().is_cancelled());
+    }
+
+    #[test]
+    fn ignore_older_searches() {
+        let searches = Searches::default();
+        searches.received(CODE, 7);
+        searches.started(CODE, 7);
+        searches.received(CODE, 6);
+        assert!(!searches.generation(CODE).token().is_cancelled());
+    }
+}
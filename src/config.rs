@@ -0,0 +1,494 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! User configuration of this service.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use log::{trace, warn};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::matcher::MatcherKind;
+
+/// Strip comments from the `contents` of a JSON file of VSCode, e.g. `settings.json`.
+///
+/// VSCode permits `//` and `/* */` comments in its JSON files.  Remove comments outside of
+/// strings, and keep line breaks, so that URLs in strings stay intact.
+pub fn strip_json_comments(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            match c {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '/' && chars.peek() == Some(&'/') {
+            if chars.by_ref().any(|c| c == '\n') {
+                stripped.push('\n');
+            }
+        } else if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut previous = None;
+            for c in chars.by_ref() {
+                if previous == Some('*') && c == '/' {
+                    break;
+                }
+                previous = Some(c);
+            }
+            stripped.push(' ');
+        } else {
+            in_string = c == '"';
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// A provider defined by the user.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct UserProvider {
+    /// A human readable label for this provider.
+    pub label: String,
+    /// The ID (that is, the filename) of the desktop file of the corresponding app.
+    pub desktop_id: String,
+    /// The configuration directory of the app.
+    ///
+    /// Relative paths are relative to the user configuration directory; paths starting with
+    /// `~/` are relative to the home directory.
+    pub config_dir: String,
+    /// The object path to expose this provider at, relative to the object path of this service.
+    pub object_path: String,
+}
+
+impl UserProvider {
+    /// Whether the object path of this provider is valid.
+    ///
+    /// The object path must consist of non-empty elements separated by `/`, and each element
+    /// must only contain ASCII letters, digits, and `_`.
+    fn has_valid_object_path(&self) -> bool {
+        self.object_path.split('/').all(|element| {
+            !element.is_empty()
+                && element
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    }
+}
+
+/// Providers defined by the user, in `providers.toml`.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct ProvidersConfig {
+    /// All providers defined by the user.
+    #[serde(default, rename = "provider")]
+    pub providers: Vec<UserProvider>,
+}
+
+impl ProvidersConfig {
+    /// Read providers from the given TOML `contents`.
+    ///
+    /// Skip providers with invalid object paths.
+    pub fn read(contents: &str) -> Result<Self> {
+        let mut config: Self = toml::from_str(contents)?;
+        config.providers.retain(|provider| {
+            let valid = provider.has_valid_object_path();
+            if !valid {
+                warn!(
+                    "Skipping provider {}: Invalid object path {}",
+                    provider.label, provider.object_path
+                );
+            }
+            valid
+        });
+        Ok(config)
+    }
+
+    /// Read the `providers.toml` file in the given `config_dir`.
+    ///
+    /// Return an empty configuration if the file does not exist.
+    pub fn from_dir<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        let path = config_dir.as_ref().join("providers.toml");
+        trace!("Reading providers from {}", path.display());
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::read(&contents)
+                .with_context(|| format!("Failed to parse providers from {}", path.display())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => {
+                Err(error).with_context(|| format!("Failed to open {} for reading", path.display()))
+            }
+        }
+    }
+
+    /// The desktop IDs of all providers, in order.
+    pub fn desktop_ids(&self) -> Vec<String> {
+        self.providers
+            .iter()
+            .map(|provider| provider.desktop_id.clone())
+            .collect()
+    }
+}
+
+/// Which window of the editor to open results in.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    /// Let the editor decide, which usually reuses the last window.
+    Default,
+    /// Always open a new window, with `--new-window`.
+    New,
+    /// Always reuse the last active window, with `--reuse-window`.
+    Reuse,
+}
+
+impl Default for WindowMode {
+    fn default() -> Self {
+        WindowMode::Default
+    }
+}
+
+impl WindowMode {
+    /// Read the window mode for folders from the `contents` of the `settings.json` of VSCode.
+    ///
+    /// Look for `window.openFoldersInNewWindow` with a regular expression after stripping
+    /// comments, because `settings.json` permits comments and trailing commas.  Return `None` if
+    /// `settings.json` doesn't set `window.openFoldersInNewWindow`, or sets it to `default`.
+    pub fn from_editor_settings(contents: &str) -> Option<Self> {
+        let setting =
+            Regex::new(r#""window\.openFoldersInNewWindow"\s*:\s*"(on|off|default)""#).unwrap();
+        setting
+            .captures_iter(&strip_json_comments(contents))
+            .last()
+            .and_then(|captures| match &captures[1] {
+                "on" => Some(WindowMode::New),
+                "off" => Some(WindowMode::Reuse),
+                _ => None,
+            })
+    }
+
+    /// The command line arguments for this window mode.
+    pub fn args(self) -> &'static [&'static str] {
+        match self {
+            WindowMode::Default => &[],
+            WindowMode::New => &["--new-window"],
+            WindowMode::Reuse => &["--reuse-window"],
+        }
+    }
+}
+
+/// General settings of this service, in `config.toml`.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct Settings {
+    /// Whether to hide remote workspaces, i.e. all workspaces which are not `file://` URLs.
+    #[serde(default)]
+    pub hide_remote: bool,
+    /// Whether to include workspaces opened on this machine with Remote - SSH, from the server
+    /// directory of VSCode, e.g. `~/.vscode-server`.
+    #[serde(default)]
+    pub index_vscode_server: bool,
+    /// Whether to match search terms against the full path of workspaces, and not just against
+    /// their names.
+    #[serde(default)]
+    pub match_path: bool,
+    /// Whether to match search terms against the `origin` remote of local git repositories.
+    #[serde(default)]
+    pub match_git_remote: bool,
+    /// The maximum number of results to return for a search, if any.
+    #[serde(default)]
+    pub max_results: Option<usize>,
+    /// The minimum number of characters in search terms before returning any results.
+    #[serde(default)]
+    pub min_query_length: usize,
+    /// Whether to show the title of the README of local workspaces in their description.
+    #[serde(default)]
+    pub show_readme_title: bool,
+    /// The desktop ID of the editor to show workspaces in, if several editors have the same
+    /// workspace.
+    #[serde(default)]
+    pub preferred_editor: Option<String>,
+    /// Icons to use instead of the app icon, by desktop ID of the app.
+    ///
+    /// Each icon is either the name of a themed icon or an absolute path to an icon file.
+    #[serde(default)]
+    pub icons: HashMap<String, String>,
+    /// The algorithm to match search terms with.
+    #[serde(default)]
+    pub matcher: MatcherKind,
+    /// Which window to open results in.
+    #[serde(default)]
+    pub window: WindowMode,
+    /// Which window to open results in, by desktop ID of the app, instead of `window`.
+    #[serde(default)]
+    pub windows: HashMap<String, WindowMode>,
+    /// Whether to open local workspaces in a terminal instead of the editor.
+    #[serde(default)]
+    pub open_in_terminal: bool,
+    /// The command to open a terminal with, which takes the directory to open as last argument.
+    ///
+    /// If `None` use the first terminal of Gnome we find.
+    #[serde(default)]
+    pub terminal: Option<String>,
+    /// The directory to clone git repositories to, from search terms like `owner/repo`.
+    ///
+    /// `None` disables cloning.  Expand a leading `~/` to the home directory.
+    #[serde(default)]
+    pub clone_dir: Option<String>,
+    /// Quit after this many minutes without searches.
+    ///
+    /// `None` keeps the service running.
+    #[serde(default)]
+    pub idle_timeout: Option<u64>,
+}
+
+impl Settings {
+    /// Read settings from the given TOML `contents`.
+    pub fn read(contents: &str) -> Result<Self> {
+        toml::from_str(contents).map_err(Into::into)
+    }
+
+    /// Read the `config.toml` file in the given `config_dir`.
+    ///
+    /// Return default settings if the file does not exist.
+    pub fn from_dir<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        let path = config_dir.as_ref().join("config.toml");
+        trace!("Reading settings from {}", path.display());
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::read(&contents)
+                .with_context(|| format!("Failed to parse settings from {}", path.display())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => {
+                Err(error).with_context(|| format!("Failed to open {} for reading", path.display()))
+            }
+        }
+    }
+
+    /// The window mode for the app with the given `desktop_id`.
+    ///
+    /// Prefer the window mode in `windows` for `desktop_id`, then `window` unless it's
+    /// `default`.  Otherwise use `editor`, i.e. the window mode in the settings of the editor
+    /// itself, if any.
+    pub fn window_mode(&self, desktop_id: &str, editor: Option<WindowMode>) -> WindowMode {
+        self.windows
+            .get(desktop_id)
+            .copied()
+            .or_else(|| Some(self.window).filter(|mode| *mode != WindowMode::Default))
+            .or(editor)
+            .unwrap_or_default()
+    }
+
+    /// These settings without window modes, for editors which do not support window flags.
+    pub fn without_window_modes(self) -> Self {
+        Self {
+            window: WindowMode::Default,
+            windows: HashMap::new(),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_providers() {
+        let config = ProvidersConfig::read(
+            r#"
+[[provider]]
+label = "My fork"
+desktop_id = "my-fork.desktop"
+config_dir = "My Fork"
+object_path = "myfork"
+
+[[provider]]
+label = "Invalid"
+desktop_id = "invalid.desktop"
+config_dir = "~/.invalid"
+object_path = "in-valid"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ProvidersConfig {
+                providers: vec![UserProvider {
+                    label: "My fork".to_string(),
+                    desktop_id: "my-fork.desktop".to_string(),
+                    config_dir: "My Fork".to_string(),
+                    object_path: "myfork".to_string(),
+                }]
+            }
+        );
+        assert_eq!(config.desktop_ids(), vec!["my-fork.desktop"]);
+    }
+
+    #[test]
+    fn read_settings() {
+        assert_eq!(
+            Settings::read("hide_remote = true\n").unwrap(),
+            Settings {
+                hide_remote: true,
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("index_vscode_server = true\n").unwrap(),
+            Settings {
+                index_vscode_server: true,
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("max_results = 10\n").unwrap(),
+            Settings {
+                max_results: Some(10),
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("min_query_length = 3\n").unwrap(),
+            Settings {
+                min_query_length: 3,
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("matcher = \"regex\"\n").unwrap(),
+            Settings {
+                matcher: MatcherKind::Regex,
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("window = \"new\"\n").unwrap(),
+            Settings {
+                window: WindowMode::New,
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("terminal = \"alacritty --working-directory\"\n").unwrap(),
+            Settings {
+                terminal: Some("alacritty --working-directory".to_string()),
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            Settings::read("idle_timeout = 15\n").unwrap(),
+            Settings {
+                idle_timeout: Some(15),
+                ..Settings::default()
+            }
+        );
+        let mut icons = HashMap::new();
+        icons.insert(
+            "code-insiders.desktop".to_string(),
+            "/home/foo/.local/share/icons/insiders.svg".to_string(),
+        );
+        assert_eq!(
+            Settings::read(
+                "[icons]\n\"code-insiders.desktop\" = \"/home/foo/.local/share/icons/insiders.svg\"\n"
+            )
+            .unwrap(),
+            Settings {
+                icons,
+                ..Settings::default()
+            }
+        );
+        assert_eq!(Settings::read("").unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn window_mode_per_editor() {
+        let settings =
+            Settings::read("window = \"new\"\n[windows]\n\"codium.desktop\" = \"reuse\"\n")
+                .unwrap();
+        assert_eq!(
+            settings.window_mode("codium.desktop", None),
+            WindowMode::Reuse
+        );
+        assert_eq!(settings.window_mode("code.desktop", None), WindowMode::New);
+        assert_eq!(
+            settings.window_mode("code.desktop", Some(WindowMode::Reuse)),
+            WindowMode::New
+        );
+        assert_eq!(
+            settings.window_mode("codium.desktop", Some(WindowMode::New)),
+            WindowMode::Reuse
+        );
+        assert_eq!(
+            Settings::default().window_mode("code.desktop", Some(WindowMode::Reuse)),
+            WindowMode::Reuse
+        );
+        assert_eq!(
+            Settings::default().window_mode("code.desktop", None),
+            WindowMode::Default
+        );
+        assert_eq!(
+            settings
+                .without_window_modes()
+                .window_mode("codium.desktop", None),
+            WindowMode::Default
+        );
+    }
+
+    #[test]
+    fn window_mode_from_editor_settings() {
+        assert_eq!(
+            WindowMode::from_editor_settings(
+                "{\n  // \"window.openFoldersInNewWindow\": \"off\",\n  \"window.openFoldersInNewWindow\": \"on\",\n}\n"
+            ),
+            Some(WindowMode::New)
+        );
+        assert_eq!(
+            WindowMode::from_editor_settings(
+                "{\n  \"window.openFoldersInNewWindow\": \"default\"\n}\n"
+            ),
+            None
+        );
+        assert_eq!(
+            WindowMode::from_editor_settings("{\n  \"editor.fontSize\": 14\n}\n"),
+            None
+        );
+        assert_eq!(
+            WindowMode::from_editor_settings(
+                r#"{"editor.fontSize":14,"window.openFoldersInNewWindow":"off"}"#
+            ),
+            Some(WindowMode::Reuse)
+        );
+        assert_eq!(
+            WindowMode::from_editor_settings(
+                "{ /* \"window.openFoldersInNewWindow\": \"on\" */ \"editor.fontSize\": 14 }"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn strip_comments_from_json() {
+        assert_eq!(
+            strip_json_comments(
+                "{\n  // Comment\n  \"url\": \"https://example.com\", /* more */\n  \"a\": \"\\\"//\"\n}"
+            ),
+            "{\n  \n  \"url\": \"https://example.com\",  \n  \"a\": \"\\\"//\"\n}"
+        );
+    }
+
+    #[test]
+    fn read_empty_providers() {
+        assert_eq!(
+            ProvidersConfig::read("").unwrap(),
+            ProvidersConfig::default()
+        );
+    }
+}
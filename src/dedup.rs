@@ -0,0 +1,85 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Deduplication of workspaces across providers.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Workspaces of all providers, shared among providers.
+#[derive(Debug, Clone, Default)]
+pub struct SharedWorkspaces {
+    /// Workspace URIs by rank of provider.
+    workspaces: Arc<Mutex<HashMap<usize, HashSet<Arc<str>>>>>,
+}
+
+impl SharedWorkspaces {
+    /// Create a deduplicator for a provider with the given `rank`.
+    ///
+    /// Providers with a lower rank take precedence.
+    pub fn deduplicator(&self, rank: usize) -> Deduplicator {
+        Deduplicator {
+            rank,
+            shared: self.clone(),
+        }
+    }
+}
+
+/// Hide workspaces which a preferred provider shows already.
+///
+/// Clones publish and check workspaces for the same provider.
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    /// The rank of this provider.
+    rank: usize,
+    /// Workspaces of all providers.
+    shared: SharedWorkspaces,
+}
+
+impl Deduplicator {
+    /// Publish the URIs of all `workspaces` of this provider.
+    ///
+    /// Providers publish whenever they load workspaces, independent of searches, because
+    /// Gnome Shell searches all providers concurrently.
+    pub fn publish(&self, workspaces: HashSet<Arc<str>>) {
+        if let Ok(mut shared) = self.shared.workspaces.lock() {
+            shared.insert(self.rank, workspaces);
+        }
+    }
+
+    /// Whether a provider with lower rank has the workspace with the given `uri`.
+    ///
+    /// Only consider workspaces which other providers published when they last loaded their
+    /// workspaces.
+    pub fn is_duplicate(&self, uri: &str) -> bool {
+        self.shared.workspaces.lock().map_or(false, |shared| {
+            shared
+                .iter()
+                .any(|(rank, workspaces)| *rank < self.rank && workspaces.contains(uri))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uris(uris: &[&str]) -> HashSet<Arc<str>> {
+        uris.iter().map(|uri| (*uri).into()).collect()
+    }
+
+    #[test]
+    fn hide_workspaces_of_preferred_providers() {
+        let shared = SharedWorkspaces::default();
+        let preferred = shared.deduplicator(0);
+        let other = shared.deduplicator(1);
+        preferred.publish(uris(&["file:///home/foo/mdcat"]));
+        other.publish(uris(&["file:///home/foo/mdcat", "file:///home/foo/sbctl"]));
+        assert!(other.is_duplicate("file:///home/foo/mdcat"));
+        assert!(!other.is_duplicate("file:///home/foo/sbctl"));
+        assert!(!preferred.is_duplicate("file:///home/foo/mdcat"));
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Discover VSCode variants among installed apps.
+
+use std::path::Path;
+
+use log::{debug, info};
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+use crate::config::UserProvider;
+use crate::objpath_element;
+
+/// Names of executables of VSCode variants.
+const VSCODE_EXECUTABLES: &[&str] = &[
+    "code",
+    "code-oss",
+    "code-insiders",
+    "codium",
+    "vscodium",
+    "cursor",
+    "windsurf",
+    "positron",
+];
+
+/// `StartupWMClass` values of VSCode variants, in lowercase.
+const VSCODE_WM_CLASSES: &[&str] = &[
+    "code",
+    "code - oss",
+    "code - insiders",
+    "vscodium",
+    "vscodium - insiders",
+    "cursor",
+    "windsurf",
+    "positron",
+];
+
+/// Whether an app looks like a VSCode variant.
+///
+/// `wm_class` is the `StartupWMClass` of the app, and `executable` the name of its executable.
+fn looks_like_vscode(wm_class: Option<&str>, executable: &str) -> bool {
+    VSCODE_EXECUTABLES.contains(&executable)
+        || wm_class.map_or(false, |wm_class| {
+            VSCODE_WM_CLASSES.contains(&wm_class.to_lowercase().as_str())
+        })
+}
+
+/// Candidates for the name of the configuration directory of an app.
+///
+/// VSCode variants typically use their `StartupWMClass` or their name as configuration
+/// directory.
+fn config_dirname_candidates<'a>(wm_class: Option<&'a str>, name: &'a str) -> Vec<&'a str> {
+    let mut candidates: Vec<&str> = wm_class.into_iter().collect();
+    if !candidates.contains(&name) {
+        candidates.push(name);
+    }
+    candidates
+}
+
+/// Discover VSCode variants among all installed apps.
+///
+/// Look for apps which look like VSCode, and which have a configuration directory with a
+/// `storage.json` in `user_config_dir`.  Skip all apps in `known_desktop_ids`.
+///
+/// Return a provider for each discovered app, at an object path below `discovered/`.
+pub fn discover_providers(user_config_dir: &Path, known_desktop_ids: &[&str]) -> Vec<UserProvider> {
+    let mut providers = Vec::new();
+    for app in gio::AppInfo::all() {
+        let desktop_id = match app.id() {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        if known_desktop_ids.contains(&desktop_id.as_str()) {
+            continue;
+        }
+        let app = match app.downcast::<gio::DesktopAppInfo>() {
+            Ok(app) => app,
+            Err(_) => continue,
+        };
+        let wm_class = app.startup_wm_class();
+        let executable = app.executable();
+        let executable_name = executable
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if !looks_like_vscode(wm_class.as_deref(), executable_name) {
+            continue;
+        }
+        debug!(
+            "App {} looks like VSCode, looking for configuration directory",
+            desktop_id
+        );
+        let name = app.name();
+        let config_dir = config_dirname_candidates(wm_class.as_deref(), &name)
+            .into_iter()
+            .find(|dirname| user_config_dir.join(dirname).join("storage.json").is_file());
+        if let Some(config_dir) = config_dir {
+            info!(
+                "Discovered VSCode variant {} with configuration in {}",
+                desktop_id, config_dir
+            );
+            providers.push(UserProvider {
+                label: format!("{} (discovered)", name),
+                object_path: format!("discovered/{}", objpath_element(&desktop_id)),
+                config_dir: config_dir.to_string(),
+                desktop_id,
+            });
+        }
+    }
+    providers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vscode_like_apps() {
+        assert!(looks_like_vscode(Some("VSCodium"), "codium"));
+        assert!(looks_like_vscode(Some("Code"), "code"));
+        assert!(looks_like_vscode(None, "cursor"));
+        assert!(!looks_like_vscode(Some("firefox"), "firefox"));
+        assert!(!looks_like_vscode(None, "gedit"));
+        assert!(looks_like_vscode(Some("Code - OSS"), "electron"));
+        assert!(!looks_like_vscode(Some("Xcode"), "xcode"));
+        assert!(!looks_like_vscode(Some("barcode-scanner"), "scanner"));
+    }
+
+    #[test]
+    fn config_dirnames() {
+        assert_eq!(
+            config_dirname_candidates(Some("Code"), "Visual Studio Code"),
+            vec!["Code", "Visual Studio Code"]
+        );
+        assert_eq!(
+            config_dirname_candidates(Some("Cursor"), "Cursor"),
+            vec!["Cursor"]
+        );
+        assert_eq!(config_dirname_candidates(None, "Foo"), vec!["Foo"]);
+    }
+}
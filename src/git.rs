@@ -0,0 +1,324 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Git repositories of local workspaces.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+/// A remote of a git repository.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GitRemote {
+    /// The host of the remote, e.g. `github.com`.
+    pub host: String,
+    /// The repository on the host, e.g. `owner/repo`.
+    pub repository: String,
+}
+
+impl GitRemote {
+    /// Parse a remote `url`.
+    ///
+    /// Support URLs like `https://github.com/owner/repo.git` and `ssh://git@github.com/owner/repo`
+    /// as well as scp-like addresses like `git@github.com:owner/repo.git`.  Return `None` for
+    /// local paths and other URLs without host.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (host, path) = match url.split_once("://") {
+            Some((_, rest)) => rest.split_once('/')?,
+            None => url.split_once(':')?,
+        };
+        // Strip user names and ports
+        let host = host.rsplit('@').next()?;
+        let host = host.split(':').next()?;
+        let repository = path
+            .trim_matches('/')
+            .trim_end_matches(".git")
+            .trim_end_matches('/');
+        if host.is_empty() || repository.is_empty() {
+            None
+        } else {
+            Some(Self {
+                host: host.to_string(),
+                repository: repository.to_string(),
+            })
+        }
+    }
+
+    /// Read the `origin` remote from the `contents` of a git config file.
+    pub fn origin_from_config(contents: &str) -> Option<Self> {
+        let mut in_origin = false;
+        for line in contents.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_origin = line == "[remote \"origin\"]";
+            } else if in_origin {
+                if let Some((key, value)) = line.split_once('=') {
+                    if key.trim() == "url" {
+                        return Self::parse(value.trim());
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Find the `origin` remote of the git repository in `workspace`.
+    ///
+    /// Return `None` if `workspace` is no git repository or has no `origin` remote.
+    pub fn origin_of(workspace: &Path) -> Option<Self> {
+        let config = common_dir(&git_dir(workspace)?).join("config");
+        trace!("Reading git remote from {}", config.display());
+        let contents = std::fs::read_to_string(config).ok()?;
+        Self::origin_from_config(&contents)
+    }
+}
+
+/// Whether `s` is a valid name of a GitHub owner or repository.
+fn is_github_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with('.')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Whether `s` is a valid user or host name in an scp-like address.
+fn is_scp_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with('-')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Whether we may pass `url` to `git clone`.
+///
+/// Only permit `https://` and `ssh://` URLs and scp-like addresses like `user@host:path`, and
+/// reject all other transports of git, e.g. `file://` or `ext::`.
+pub fn is_clone_url(url: &str) -> bool {
+    if url.starts_with('-') || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    match url.split_once("://") {
+        Some((scheme, _)) => {
+            matches!(scheme.to_ascii_lowercase().as_str(), "https" | "ssh")
+                && GitRemote::parse(url).is_some()
+        }
+        None => url
+            .split_once(':')
+            .and_then(|(address, path)| {
+                let (user, host) = address.split_once('@')?;
+                Some(is_scp_name(user) && is_scp_name(host) && !path.is_empty())
+            })
+            .unwrap_or(false),
+    }
+}
+
+/// The URL to clone a repository from, for the given search `query`.
+///
+/// Accept `https://` and `ssh://` URLs and scp-like addresses, and `owner/repo` as a shorthand
+/// for a repository on GitHub.  Return `None` if `query` looks like neither.
+pub fn clone_url(query: &str) -> Option<(String, GitRemote)> {
+    if query.contains("://") || query.contains('@') {
+        if is_clone_url(query) {
+            GitRemote::parse(query).map(|remote| (query.to_string(), remote))
+        } else {
+            None
+        }
+    } else {
+        let (owner, repo) = query.split_once('/')?;
+        if is_github_name(owner) && is_github_name(repo) {
+            Some((
+                format!("https://github.com/{}/{}.git", owner, repo),
+                GitRemote {
+                    host: "github.com".to_string(),
+                    repository: format!("{}/{}", owner, repo),
+                },
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// The git directory of the repository at `workspace`.
+///
+/// Follow `.git` files of submodules and worktrees.
+fn git_dir(workspace: &Path) -> Option<PathBuf> {
+    let dot_git = workspace.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = std::fs::read_to_string(&dot_git).ok()?;
+    Some(workspace.join(contents.trim().strip_prefix("gitdir:")?.trim()))
+}
+
+/// The common git directory for `git_dir`, which has the config.
+///
+/// Worktrees have their own git directory, which points to the common directory of the main
+/// repository.
+fn common_dir(git_dir: &Path) -> PathBuf {
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(commondir) => git_dir.join(commondir.trim()),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+/// Describe the checked out commit from the `contents` of `HEAD`.
+///
+/// Return the branch name, or the abbreviated commit hash for a detached `HEAD`.
+fn describe_head(contents: &str) -> Option<String> {
+    let head = contents.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(
+                reference
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(reference)
+                    .to_string(),
+            )
+        }
+        None if !head.is_empty() => Some(head.chars().take(7).collect()),
+        None => None,
+    }
+}
+
+/// A cache of the current branches of git repositories.
+///
+/// Remember the branch of each repository along with the modification time of its `HEAD`, to
+/// only read `HEAD` again after it changed.
+#[derive(Debug, Default)]
+pub struct BranchCache {
+    /// The modification time of `HEAD` and the branch, by workspace.
+    branches: HashMap<PathBuf, (SystemTime, Option<String>)>,
+}
+
+impl BranchCache {
+    /// The current branch of the git repository in `workspace`.
+    ///
+    /// Return `None` if `workspace` is no git repository.
+    pub fn branch(&mut self, workspace: &Path) -> Option<String> {
+        let head = git_dir(workspace)?.join("HEAD");
+        let mtime = std::fs::metadata(&head)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if let Some((cached_mtime, branch)) = self.branches.get(workspace) {
+            if *cached_mtime == mtime {
+                return branch.clone();
+            }
+        }
+        trace!("Reading current branch from {}", head.display());
+        let branch = std::fs::read_to_string(&head)
+            .ok()
+            .and_then(|contents| describe_head(&contents));
+        self.branches
+            .insert(workspace.to_path_buf(), (mtime, branch.clone()));
+        branch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_remote_urls() {
+        let remote = GitRemote {
+            host: "github.com".to_string(),
+            repository: "swsnr/mdcat".to_string(),
+        };
+        assert_eq!(
+            GitRemote::parse("https://github.com/swsnr/mdcat.git"),
+            Some(remote.clone())
+        );
+        assert_eq!(
+            GitRemote::parse("git@github.com:swsnr/mdcat.git"),
+            Some(remote.clone())
+        );
+        assert_eq!(
+            GitRemote::parse("ssh://git@github.com:22/swsnr/mdcat/"),
+            Some(remote)
+        );
+        assert_eq!(GitRemote::parse("/srv/git/mdcat.git"), None);
+    }
+
+    #[test]
+    fn clone_urls() {
+        let remote = GitRemote {
+            host: "github.com".to_string(),
+            repository: "swsnr/mdcat".to_string(),
+        };
+        assert_eq!(
+            clone_url("swsnr/mdcat"),
+            Some((
+                "https://github.com/swsnr/mdcat.git".to_string(),
+                remote.clone()
+            ))
+        );
+        assert_eq!(
+            clone_url("git@github.com:swsnr/mdcat.git"),
+            Some(("git@github.com:swsnr/mdcat.git".to_string(), remote))
+        );
+        assert_eq!(clone_url("work/acme/billing"), None);
+        assert_eq!(clone_url("mdcat"), None);
+        assert_eq!(clone_url("file:///home/foo/mdcat"), None);
+        assert_eq!(clone_url("foo@example.com"), None);
+    }
+
+    #[test]
+    fn clone_url_transports() {
+        assert!(is_clone_url("https://github.com/swsnr/mdcat.git"));
+        assert!(is_clone_url("ssh://git@github.com/swsnr/mdcat"));
+        assert!(is_clone_url("git@github.com:swsnr/mdcat.git"));
+        assert!(!is_clone_url("http://github.com/swsnr/mdcat.git"));
+        assert!(!is_clone_url("file:///srv/git/mdcat.git"));
+        assert!(!is_clone_url("ext::sh -c touch% /tmp/pwned"));
+        assert!(!is_clone_url("git://github.com/swsnr/mdcat.git"));
+        assert!(!is_clone_url("-u@host:path"));
+        assert!(!is_clone_url("git@github.com:swsnr/md cat"));
+        assert!(!is_clone_url("/srv/git/mdcat.git"));
+    }
+
+    #[test]
+    fn describe_heads() {
+        assert_eq!(
+            describe_head("ref: refs/heads/main\n").as_deref(),
+            Some("main")
+        );
+        assert_eq!(
+            describe_head("ref: refs/heads/feature/foo\n").as_deref(),
+            Some("feature/foo")
+        );
+        assert_eq!(
+            describe_head("0123456789abcdef0123456789abcdef01234567\n").as_deref(),
+            Some("0123456")
+        );
+        assert_eq!(describe_head(""), None);
+    }
+
+    #[test]
+    fn read_origin_from_config() {
+        let config = r#"
+[core]
+	bare = false
+[remote "upstream"]
+	url = https://gitlab.com/upstream/mdcat.git
+[remote "origin"]
+	url = git@github.com:swsnr/mdcat.git
+	fetch = +refs/heads/*:refs/remotes/origin/*
+"#;
+        assert_eq!(
+            GitRemote::origin_from_config(config),
+            Some(GitRemote {
+                host: "github.com".to_string(),
+                repository: "swsnr/mdcat".to_string(),
+            })
+        );
+        assert_eq!(GitRemote::origin_from_config("[core]\n"), None);
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The history of workspaces launched from search results.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use log::trace;
+
+/// How many launches to remember per workspace.
+const MAX_LAUNCHES: usize = 10;
+
+/// The number of seconds in a day.
+const DAY: u64 = 24 * 60 * 60;
+
+/// The weight of a launch by its maximum age in days, from recent to old.
+///
+/// Launches older than the last bucket get [`OLD_LAUNCH_WEIGHT`].
+const LAUNCH_WEIGHTS: [(u64, i64); 4] = [(4, 100), (14, 70), (31, 50), (90, 30)];
+
+/// The weight of launches older than any bucket in [`LAUNCH_WEIGHTS`].
+const OLD_LAUNCH_WEIGHT: i64 = 10;
+
+/// The current time in seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The directory for state of this service, i.e. `$XDG_STATE_HOME/gnome-search-providers-vscode`.
+///
+/// `home_dir` is the home directory of the current user, to fall back to `~/.local/state` if
+/// `$XDG_STATE_HOME` is unset.
+pub fn state_dir(home_dir: &Path) -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home_dir.join(".local").join("state"))
+        .join(env!("CARGO_PKG_NAME"))
+}
+
+/// The launch history of a search provider.
+#[derive(Debug, Default)]
+pub struct LaunchHistory {
+    /// The file to persist the history in, if any.
+    path: Option<PathBuf>,
+    /// Launch times in seconds since the epoch, most recent last, by result ID.
+    launches: HashMap<String, Vec<u64>>,
+}
+
+impl LaunchHistory {
+    /// Read the launch history from the file at `path`.
+    ///
+    /// Return an empty history if the file doesn't exist yet.
+    pub fn from_file(path: PathBuf) -> Result<Self> {
+        trace!("Reading launch history from {}", path.display());
+        let launches = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse launch history {}", path.display()))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Failed to open {} for reading", path.display()))
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            launches,
+        })
+    }
+
+    /// Record a launch of the result with the given `id` at `time`.
+    pub fn record(&mut self, id: &str, time: u64) {
+        let launches = self.launches.entry(id.to_string()).or_default();
+        launches.push(time);
+        if MAX_LAUNCHES < launches.len() {
+            launches.drain(..launches.len() - MAX_LAUNCHES);
+        }
+    }
+
+    /// Write the history back to its file, if any.
+    pub fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+            }
+            let contents = serde_json::to_string(&self.launches)?;
+            std::fs::write(path, contents)
+                .with_context(|| format!("Failed to write launch history to {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// How frequently and recently the result with the given `id` was launched as of `now`.
+    ///
+    /// Sum the weights of all remembered launches, where recent launches weigh more than old
+    /// ones.  Return 0 if the result was never launched.
+    pub fn frecency(&self, id: &str, now: u64) -> i64 {
+        self.launches.get(id).map_or(0, |launches| {
+            launches
+                .iter()
+                .map(|time| {
+                    let age = now.saturating_sub(*time) / DAY;
+                    LAUNCH_WEIGHTS
+                        .iter()
+                        .find(|(max_age, _)| age <= *max_age)
+                        .map_or(OLD_LAUNCH_WEIGHT, |(_, weight)| *weight)
+                })
+                .sum()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frecency_of_unknown_result() {
+        assert_eq!(LaunchHistory::default().frecency("foo", 1000), 0);
+    }
+
+    #[test]
+    fn frecency_prefers_recent_launches() {
+        let now = 1000 * DAY;
+        let mut history = LaunchHistory::default();
+        history.record("recent", now - DAY);
+        history.record("old", now - 100 * DAY);
+        history.record("old", now - 120 * DAY);
+        assert_eq!(history.frecency("recent", now), 100);
+        assert_eq!(history.frecency("old", now), 20);
+    }
+
+    #[test]
+    fn record_remembers_last_launches() {
+        let mut history = LaunchHistory::default();
+        for time in 0..20 {
+            history.record("foo", time);
+        }
+        assert_eq!(history.launches["foo"], (10..20).collect::<Vec<_>>());
+        assert_eq!(history.frecency("foo", 20), 1000);
+    }
+}
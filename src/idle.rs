@@ -0,0 +1,64 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Quit the service after a while without requests.
+//!
+//! DBus starts this service again on the next search, so it needn't stay around while nobody
+//! searches.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use gnome_search_provider_common::export::gio::glib;
+
+/// How often to check whether the service is idle, in seconds.
+const CHECK_INTERVAL: u32 = 60;
+
+/// When the service last handled a request.
+///
+/// Clones share the time of the last request.
+#[derive(Debug, Clone)]
+pub struct Activity(Rc<Cell<Instant>>);
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self(Rc::new(Cell::new(Instant::now())))
+    }
+}
+
+impl Activity {
+    /// Record a request.
+    pub fn touch(&self) {
+        self.0.set(Instant::now());
+    }
+
+    /// How long since the last request.
+    fn idle_for(&self) -> Duration {
+        self.0.get().elapsed()
+    }
+}
+
+/// Quit `mainloop` if `activity` saw no request for `timeout`.
+///
+/// `timeout` may change while the service runs, e.g. when reloading settings; `None` keeps the
+/// service running.
+pub fn quit_when_idle(
+    mainloop: glib::MainLoop,
+    activity: Activity,
+    timeout: Rc<Cell<Option<Duration>>>,
+) {
+    glib::timeout_add_seconds_local(CHECK_INTERVAL, move || match timeout.get() {
+        Some(timeout) if timeout <= activity.idle_for() => {
+            info!("No requests for {:?}, quitting", timeout);
+            mainloop.quit();
+            glib::Continue(false)
+        }
+        _ => glib::Continue(true),
+    });
+}
@@ -8,254 +8,2234 @@
 
 //! Gnome search provider for VSCode editors.
 
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{anyhow, Context, Error, Result};
-use log::{error, info, trace, warn};
+use anyhow::{anyhow, Context, Result};
+use futures_util::{FutureExt, StreamExt};
+use log::{debug, error, info, trace, warn};
 use serde::Deserialize;
 
 use gnome_search_provider_common::app::*;
 use gnome_search_provider_common::dbus::*;
 use gnome_search_provider_common::export::gio;
 use gnome_search_provider_common::export::gio::glib;
-use gnome_search_provider_common::export::zbus;
+use gnome_search_provider_common::export::gio::prelude::*;
 use gnome_search_provider_common::export::zbus::export::names::WellKnownName;
 use gnome_search_provider_common::log::*;
 use gnome_search_provider_common::mainloop::*;
 use gnome_search_provider_common::matching::*;
+use zbus::fdo::{RequestNameFlags, RequestNameReply};
 
+use crate::apps::AppRegistry;
+use crate::cache::{cache_dir, CachedWorkspaces};
+use crate::cancel::Searches;
+use crate::config::{strip_json_comments, ProvidersConfig, Settings, UserProvider};
+use crate::dedup::{Deduplicator, SharedWorkspaces};
+use crate::discovery::discover_providers;
+use crate::git::GitRemote;
+use crate::history::{state_dir, LaunchHistory};
+use crate::idle::{quit_when_idle, Activity};
+use crate::mounts::NetworkMounts;
+use crate::provider::{
+    WorkspaceItem, WorkspaceSearchProvider, WorkspaceTarget, Workspaces, WorkspacesSource,
+};
+use crate::remote::{percent_decode, remote_path, Remote};
+use crate::session::SessionEnvironment;
+use crate::storage::{
+    storage_paths, storage_stamps, workspace_storage, BackupWorkspaces, FileStamp, RecentKind,
+    RecentWorkspace, Storage, TheiaRecentWorkspaces, CODE_WORKSPACE_EXTENSION,
+};
+use crate::watch::FileWatch;
+
+mod activation;
+mod apps;
+mod cache;
+mod cancel;
+mod config;
+mod dedup;
+mod discovery;
+mod git;
+mod history;
+mod idle;
+mod matcher;
+mod mounts;
+mod notifications;
+mod project;
+mod provider;
+mod remote;
+mod search;
+mod session;
+mod storage;
+mod watch;
+
+/// Where a VSCode variant keeps its configuration.
+///
+/// Each location has a list of candidate directory names, in order of preference; different
+/// builds of the same VSCode variant sometimes use different configuration directories.
+#[derive(Debug, Copy, Clone)]
+enum ConfigLocation<'a> {
+    /// A directory in the user configuration directory, i.e. `$XDG_CONFIG_HOME`.
+    User { dirnames: &'a [&'a str] },
+    /// A directory in the configuration directory of a Flatpak app.
+    ///
+    /// Flatpak redirects `$XDG_CONFIG_HOME` to `~/.var/app/<app_id>/config` inside its sandbox.
+    Flatpak {
+        app_id: &'a str,
+        dirnames: &'a [&'a str],
+    },
+    /// A directory in the configuration directory of a Snap.
+    ///
+    /// Strictly confined snaps redirect `$XDG_CONFIG_HOME` to `~/snap/<name>/current/.config`;
+    /// classic snaps use the user configuration directory.  We prefer the former, and fall back
+    /// to the latter.
+    Snap {
+        name: &'a str,
+        dirnames: &'a [&'a str],
+    },
+    /// A directory in the home directory of the user.
+    Home { dirnames: &'a [&'a str] },
+}
+
+impl ConfigLocation<'_> {
+    /// Resolve this location to absolute candidate directories, in order of preference.
+    ///
+    /// `user_config_dir` is the configuration directory of the current user, and
+    /// `home_dir` their home directory.
+    fn resolve(&self, user_config_dir: &Path, home_dir: &Path) -> Vec<PathBuf> {
+        match self {
+            ConfigLocation::User { dirnames } => dirnames
+                .iter()
+                .map(|dirname| user_config_dir.join(dirname))
+                .collect(),
+            ConfigLocation::Flatpak { app_id, dirnames } => {
+                let flatpak_config_dir = home_dir
+                    .join(".var")
+                    .join("app")
+                    .join(app_id)
+                    .join("config");
+                dirnames
+                    .iter()
+                    .map(|dirname| flatpak_config_dir.join(dirname))
+                    .collect()
+            }
+            ConfigLocation::Snap { name, dirnames } => {
+                let snap_config_dir = home_dir
+                    .join("snap")
+                    .join(name)
+                    .join("current")
+                    .join(".config");
+                dirnames
+                    .iter()
+                    .map(|dirname| snap_config_dir.join(dirname))
+                    .chain(dirnames.iter().map(|dirname| user_config_dir.join(dirname)))
+                    .collect()
+            }
+            ConfigLocation::Home { dirnames } => dirnames
+                .iter()
+                .map(|dirname| home_dir.join(dirname))
+                .collect(),
+        }
+    }
+}
+
+/// Product information of a VSCode variant, from `product.json`.
 #[derive(Debug, Deserialize)]
-struct StorageOpenedPathsListEntry {
-    #[serde(rename = "folderUri")]
-    folder_uri: Option<String>,
-    #[serde(rename = "fileUri")]
-    file_uri: Option<String>,
+struct Product {
+    /// The full name of the product.
+    #[serde(rename = "nameLong")]
+    name_long: String,
+    /// The quality of this build, e.g. `stable` or `insider`.
+    quality: Option<String>,
+}
+
+impl Product {
+    /// Read `product.json` from the given `reader`.
+    fn read<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(Into::into)
+    }
+
+    /// Read the `product.json` file in the given `app_dir`.
+    fn from_dir<P: AsRef<Path>>(app_dir: P) -> Result<Self> {
+        let path = app_dir.as_ref().join("product.json");
+        trace!("Reading product information from {}", path.display());
+        Self::read(
+            File::open(&path)
+                .with_context(|| format!("Failed to open {} for reading", path.display()))?,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to parse product information from {}",
+                path.display()
+            )
+        })
+    }
+
+    /// A human readable label for this product.
+    ///
+    /// Add the quality, unless this is a stable build or the name already mentions it.
+    fn label(&self) -> String {
+        match self.quality.as_deref() {
+            Some(quality)
+                if quality != "stable"
+                    && !self
+                        .name_long
+                        .to_lowercase()
+                        .contains(&quality.to_lowercase()) =>
+            {
+                format!("{} ({})", self.name_long, quality)
+            }
+            _ => self.name_long.clone(),
+        }
+    }
+}
+
+/// Candidates for the directory containing `product.json` of a VSCode `executable`.
+///
+/// VSCode keeps `product.json` in `resources/app` next to its executable, or next to the
+/// directory of its executable, for the `bin/code` wrapper script.
+fn product_dirs(executable: &Path) -> Vec<PathBuf> {
+    let executable_dir = executable.parent();
+    executable_dir
+        .into_iter()
+        .chain(executable_dir.and_then(Path::parent))
+        .map(|dir| dir.join("resources").join("app"))
+        .collect()
+}
+
+/// Find product information for the given `app`.
+fn find_product(app: &gio::DesktopAppInfo) -> Option<Product> {
+    let executable = find_executable(app)?;
+    product_dirs(&executable)
+        .into_iter()
+        .filter(|dir| dir.join("product.json").is_file())
+        .find_map(|dir| {
+            Product::from_dir(dir)
+                .map_err(|error| warn!("Ignoring product.json: {:#}", error))
+                .ok()
+        })
+}
+
+/// Find a custom user data directory in the command line `args` of VSCode.
+fn user_data_dir_from_args<I, S>(args: I) -> Option<PathBuf>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.as_ref();
+        if arg == "--user-data-dir" {
+            return args.next().map(|dir| PathBuf::from(dir.as_ref()));
+        } else if let Some(dir) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix("--user-data-dir="))
+        {
+            return Some(PathBuf::from(dir));
+        }
+    }
+    None
+}
+
+/// Custom command line arguments of VSCode in `argv.json`.
+#[derive(Debug, Default, Deserialize)]
+struct Argv {
+    #[serde(rename = "user-data-dir")]
+    user_data_dir: Option<PathBuf>,
+}
+
+impl Argv {
+    /// Read `argv.json` from the given `contents`.
+    ///
+    /// VSCode permits comments in `argv.json`, so strip comments before parsing.
+    fn read(contents: &str) -> Result<Self> {
+        serde_json::from_str(&strip_json_comments(contents)).map_err(Into::into)
+    }
+
+    /// Read the `argv.json` file in the given `data_dir`.
+    fn from_dir<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        let path = data_dir.as_ref().join("argv.json");
+        trace!("Reading custom arguments from {}", path.display());
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to open {} for reading", path.display()))?;
+        Self::read(&contents)
+            .with_context(|| format!("Failed to parse arguments from {}", path.display()))
+    }
+}
+
+/// Find custom user data directories of the given `app`.
+///
+/// Look for `--user-data-dir` in the command line of the desktop file of `app`, and for
+/// `user-data-dir` in `argv.json` in the `data_folder_name` in the `home_dir`.  Resolve
+/// relative directories and directories starting with `~` against `home_dir`.
+fn find_custom_user_data_dirs(
+    app: &gio::DesktopAppInfo,
+    home_dir: &Path,
+    data_folder_name: Option<&str>,
+) -> Vec<PathBuf> {
+    let from_commandline = app
+        .commandline()
+        .and_then(|commandline| glib::shell_parse_argv(commandline).ok())
+        .and_then(user_data_dir_from_args);
+    let from_argv = data_folder_name.and_then(|name| {
+        let data_dir = home_dir.join(name);
+        if data_dir.join("argv.json").is_file() {
+            Argv::from_dir(&data_dir)
+                .map_err(|error| warn!("Ignoring argv.json: {:#}", error))
+                .ok()
+                .and_then(|argv| argv.user_data_dir)
+        } else {
+            None
+        }
+    });
+    from_commandline
+        .into_iter()
+        .chain(from_argv)
+        .map(|dir| home_dir.join(dir.strip_prefix("~").unwrap_or(&dir)))
+        .inspect(|dir| debug!("Found custom user data dir {}", dir.display()))
+        .collect()
+}
+
+/// Candidates for the configuration directory of a portable installation of VSCode.
+///
+/// VSCode runs in portable mode if `$VSCODE_PORTABLE` is set, or if there's a `data` directory
+/// next to its `executable` (or next to the directory of its `executable`, for the `bin/code`
+/// wrapper script).  In portable mode VSCode keeps its configuration in the `user-data`
+/// directory of the portable data directory.
+///
+/// `portable_dir` is the value of `$VSCODE_PORTABLE`, if any.
+fn portable_config_dirs(executable: &Path, portable_dir: Option<&Path>) -> Vec<PathBuf> {
+    let executable_dir = executable.parent();
+    portable_dir
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(executable_dir.map(|dir| dir.join("data")))
+        .chain(
+            executable_dir
+                .and_then(Path::parent)
+                .map(|dir| dir.join("data")),
+        )
+        .map(|dir| dir.join("user-data"))
+        .collect()
+}
+
+/// Find the executable of the given `app`.
+///
+/// Look up the executable in `$PATH` if required, and resolve all symlinks.
+fn find_executable(app: &gio::DesktopAppInfo) -> Option<PathBuf> {
+    let executable = app.executable();
+    let executable = if executable.is_absolute() {
+        Some(executable)
+    } else {
+        glib::find_program_in_path(&executable)
+    };
+    executable.and_then(|executable| executable.canonicalize().ok())
+}
+
+/// Find the portable configuration directories of the given `app`.
+///
+/// See [`portable_config_dirs`].
+fn find_portable_config_dirs(app: &gio::DesktopAppInfo) -> Vec<PathBuf> {
+    match find_executable(app) {
+        Some(executable) => portable_config_dirs(
+            &executable,
+            std::env::var_os("VSCODE_PORTABLE").as_ref().map(Path::new),
+        ),
+        None => Vec::new(),
+    }
+}
+
+/// Read from the first of the given `dirs` which exists and which `read` succeeds on.
+///
+/// Return the error of the last directory `read` failed on, if any.
+fn read_from_first_dir<T, F>(dirs: &[PathBuf], read: F) -> Result<T>
+where
+    F: Fn(&Path) -> Result<T>,
+{
+    let mut last_error = None;
+    for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+        match read(dir) {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                debug!("Skipping {}: {:#}", dir.display(), error);
+                last_error = Some(error);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        anyhow!(
+            "None of the configuration directories exists: {}",
+            dirs.iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }))
+}
+
+/// Turn a desktop ID into an element of an object path.
+///
+/// Strip the `.desktop` extension and replace all characters not permitted in object paths.
+fn objpath_element(desktop_id: &str) -> String {
+    desktop_id
+        .trim_end_matches(".desktop")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// A search provider to expose from this service.
+struct ProviderDefinition<'a> {
+    /// A human readable label for this provider.
+    label: &'a str,
+    /// The IDs (that is, the filenames) of the desktop file of the corresponding app.
+    ///
+    /// Different distributions ship the same app under different desktop IDs; we register
+    /// the provider for the first desktop ID which exists.
+    desktop_ids: &'a [&'a str],
+    /// The relative object path to expose this provider at.
+    relative_obj_path: &'a str,
+    /// The name of the data folder of this app in the home directory, if any.
+    ///
+    /// This folder contains `argv.json` with custom command line arguments.
+    data_folder_name: Option<&'a str>,
+    /// The location of the configuration for this app.
+    config: ConfigLocation<'a>,
+}
+
+impl ProviderDefinition<'_> {
+    /// Gets the full object path for this provider and the given `desktop_id`.
+    ///
+    /// The first desktop ID gets the relative object path of this provider; every other desktop
+    /// ID gets an object path below, derived from the desktop ID, to make sure that the object
+    /// path is unique for each desktop ID.
+    fn objpath(&self, desktop_id: &str) -> String {
+        if self.desktop_ids.first() == Some(&desktop_id) {
+            format!("/de/swsnr/searchprovider/vscode/{}", self.relative_obj_path)
+        } else {
+            format!(
+                "/de/swsnr/searchprovider/vscode/{}/{}",
+                self.relative_obj_path,
+                objpath_element(desktop_id)
+            )
+        }
+    }
+
+    /// Get a human readable label for this provider with the given `app`.
+    ///
+    /// Use the name from `product.json` of `app` if possible, and fall back to the label of
+    /// this definition otherwise.
+    fn label_for(&self, app: &gio::DesktopAppInfo) -> String {
+        find_product(app).map_or_else(|| self.label.to_string(), |product| product.label())
+    }
+
+    /// Find the app of this provider among installed `apps`.
+    ///
+    /// Return the first desktop ID which exists, together with its app.
+    fn find_app(&self, apps: &AppRegistry) -> Option<(&str, gio::DesktopAppInfo)> {
+        self.desktop_ids
+            .iter()
+            .find_map(|id| apps.lookup(id).map(|app| (*id, app)))
+    }
+}
+
+/// Known search providers.
+///
+/// For each desktop ID of each definition in this array a corresponding provider file must exist
+/// in `providers/`; the file must refer to the same desktop ID and the corresponding object path.
+/// The object path must be unique for each desktop ID, to ensure that this service always
+/// launches the right application associated with the search provider.
+const PROVIDERS: &[ProviderDefinition] = &[
+    // The standard Arch Linux code package from community
+    ProviderDefinition {
+        label: "Code OSS (Arch Linux)",
+        desktop_ids: &["code-oss.desktop"],
+        relative_obj_path: "arch/codeoss",
+        data_folder_name: Some(".vscode-oss"),
+        config: ConfigLocation::User {
+            dirnames: &["Code - OSS", "Code"],
+        },
+    },
+    // Visual studio code from Microsoft; the binary AUR package (https://aur.archlinux.org/packages/visual-studio-code-bin/)
+    // uses visual-studio-code.desktop, the official DEB and RPM packages as well as nixpkgs use
+    // code.desktop, and some other distributions use vscode.desktop
+    ProviderDefinition {
+        label: "Visual Studio Code",
+        desktop_ids: &[
+            "visual-studio-code.desktop",
+            "code.desktop",
+            "vscode.desktop",
+        ],
+        relative_obj_path: "aur/visualstudiocode",
+        data_folder_name: Some(".vscode"),
+        config: ConfigLocation::User {
+            dirnames: &["Code"],
+        },
+    },
+    // The binary AUR package for vscodium: https://aur.archlinux.org/packages/vscodium-bin
+    // and likely other VSCodium packages as well, the Desktop file seems to be fairly standard.
+    // nixpkgs installs codium.desktop, and some other distributions use vscodium.desktop
+    ProviderDefinition {
+        label: "VSCodium",
+        desktop_ids: &["VSCodium.desktop", "codium.desktop", "vscodium.desktop"],
+        relative_obj_path: "vscodium",
+        data_folder_name: Some(".vscode-oss"),
+        config: ConfigLocation::User {
+            dirnames: &["VSCodium"],
+        },
+    },
+    // The insiders build from Microsoft: https://code.visualstudio.com/insiders/
+    ProviderDefinition {
+        label: "Visual Studio Code - Insiders",
+        desktop_ids: &["code-insiders.desktop"],
+        relative_obj_path: "insiders",
+        data_folder_name: Some(".vscode-insiders"),
+        config: ConfigLocation::User {
+            dirnames: &["Code - Insiders"],
+        },
+    },
+    // The official Flatpak for visual studio code: https://flathub.org/apps/details/com.visualstudio.code
+    ProviderDefinition {
+        label: "Visual Studio Code (Flatpak)",
+        desktop_ids: &["com.visualstudio.code.desktop"],
+        relative_obj_path: "flatpak/visualstudiocode",
+        data_folder_name: Some(".vscode"),
+        config: ConfigLocation::Flatpak {
+            app_id: "com.visualstudio.code",
+            dirnames: &["Code"],
+        },
+    },
+    // The Code OSS Flatpak: https://flathub.org/apps/details/com.visualstudio.code-oss
+    ProviderDefinition {
+        label: "Code OSS (Flatpak)",
+        desktop_ids: &["com.visualstudio.code-oss.desktop"],
+        relative_obj_path: "flatpak/codeoss",
+        data_folder_name: Some(".vscode-oss"),
+        config: ConfigLocation::Flatpak {
+            app_id: "com.visualstudio.code-oss",
+            dirnames: &["Code - OSS"],
+        },
+    },
+    // The VSCodium Flatpak: https://flathub.org/apps/details/com.vscodium.codium
+    ProviderDefinition {
+        label: "VSCodium (Flatpak)",
+        desktop_ids: &["com.vscodium.codium.desktop"],
+        relative_obj_path: "flatpak/vscodium",
+        data_folder_name: Some(".vscode-oss"),
+        config: ConfigLocation::Flatpak {
+            app_id: "com.vscodium.codium",
+            dirnames: &["VSCodium"],
+        },
+    },
+    // The official snap for visual studio code: https://snapcraft.io/code
+    ProviderDefinition {
+        label: "Visual Studio Code (Snap)",
+        desktop_ids: &["code_code.desktop"],
+        relative_obj_path: "snap/visualstudiocode",
+        data_folder_name: Some(".vscode"),
+        config: ConfigLocation::Snap {
+            name: "code",
+            dirnames: &["Code"],
+        },
+    },
+    // Cursor, a VSCode fork: https://cursor.com
+    ProviderDefinition {
+        label: "Cursor",
+        desktop_ids: &["cursor.desktop"],
+        relative_obj_path: "cursor",
+        data_folder_name: Some(".cursor"),
+        config: ConfigLocation::User {
+            dirnames: &["Cursor"],
+        },
+    },
+    // Windsurf, a VSCode fork: https://windsurf.com
+    ProviderDefinition {
+        label: "Windsurf",
+        desktop_ids: &["windsurf.desktop"],
+        relative_obj_path: "windsurf",
+        data_folder_name: Some(".windsurf"),
+        config: ConfigLocation::User {
+            dirnames: &["Windsurf"],
+        },
+    },
+    // Positron, a data science IDE based on VSCode: https://positron.posit.co
+    ProviderDefinition {
+        label: "Positron",
+        desktop_ids: &["positron.desktop"],
+        relative_obj_path: "positron",
+        data_folder_name: Some(".positron"),
+        config: ConfigLocation::User {
+            dirnames: &["Positron"],
+        },
+    },
+    // The exploration build from Microsoft
+    ProviderDefinition {
+        label: "Visual Studio Code - Exploration",
+        desktop_ids: &["code-exploration.desktop"],
+        relative_obj_path: "exploration",
+        data_folder_name: Some(".vscode-exploration"),
+        config: ConfigLocation::User {
+            dirnames: &["Code - Exploration"],
+        },
+    },
+];
+
+/// The code-server provider.
+///
+/// code-server runs VSCode in the browser, and doesn't have a desktop file of its own, so we ship
+/// a desktop file which opens URLs in the default browser, and use that to open workspaces in
+/// code-server.
+///
+/// The `config` location refers to the directory of code-server's `config.yaml`; code-server
+/// keeps its state in the user data directory.
+const CODE_SERVER: ProviderDefinition = ProviderDefinition {
+    label: "code-server",
+    desktop_ids: &["de.swsnr.searchprovider.vscode.code-server.desktop"],
+    relative_obj_path: "codeserver",
+    data_folder_name: None,
+    config: ConfigLocation::User {
+        dirnames: &["code-server"],
+    },
+};
+
+/// The Theia provider.
+///
+/// Theia isn't a VSCode variant, but it has a similar workspace model, and keeps track of recent
+/// workspaces in a simple JSON file.
+const THEIA: ProviderDefinition = ProviderDefinition {
+    label: "Theia IDE",
+    desktop_ids: &["theia-ide.desktop"],
+    relative_obj_path: "theia",
+    data_folder_name: None,
+    config: ConfigLocation::Home {
+        dirnames: &[".theia"],
+    },
+};
+
+/// All providers exposed by this service.
+fn all_providers() -> impl Iterator<Item = &'static ProviderDefinition<'static>> {
+    PROVIDERS
+        .iter()
+        .chain(std::iter::once(&CODE_SERVER))
+        .chain(std::iter::once(&THEIA))
+}
+
+/// The address code-server binds to by default.
+const CODE_SERVER_DEFAULT_BIND_ADDR: &str = "127.0.0.1:8080";
+
+/// The decoded path of the workspace at `url`, for local and remote workspaces.
+fn workspace_path(url: &str) -> Option<String> {
+    url.strip_prefix("file://")
+        .or_else(|| remote_path(url))
+        .map(percent_decode)
+}
+
+/// Split `path` into segments for matching.
+///
+/// Only consider segments below `home_dir`, if `path` is inside `home_dir`; otherwise every
+/// workspace in the home directory would match the name of the user.
+fn path_segments(path: &str, home_dir: Option<&Path>) -> Vec<String> {
+    let path = Path::new(path);
+    let relative = home_dir
+        .and_then(|home_dir| path.strip_prefix(home_dir).ok())
+        .unwrap_or(path);
+    relative
+        .iter()
+        .filter(|segment| *segment != "/")
+        .map(|segment| segment.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// The last two segments of `path`, e.g. `acme/billing`, to match `owner/repo`-style terms.
+///
+/// Return `None` if `path` has less than two segments.
+fn path_suffix(path: &str) -> Option<String> {
+    let mut segments = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .filter(|segment| !segment.is_empty());
+    let last = segments.next()?;
+    let parent = segments.next()?;
+    Some(format!("{}/{}", parent, last))
+}
+
+/// Describe the location of the workspace at `path` by its parent directory.
+///
+/// Abbreviate `home_dir` to `~`, e.g. `~/work/acme` for `/home/foo/work/acme/billing`.  Return
+/// `None` if `path` has no parent directory.
+fn describe_path(path: &str, home_dir: Option<&Path>) -> Option<String> {
+    let parent = Path::new(path.trim_end_matches('/')).parent()?;
+    let description = match home_dir.and_then(|home_dir| parent.strip_prefix(home_dir).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => parent.display().to_string(),
+    };
+    Some(description)
+}
+
+/// The ID of the workspace at `url`, for a provider whose IDs start with `prefix`.
+fn workspace_id(prefix: &str, url: &str) -> Arc<str> {
+    [prefix, url].concat().into()
+}
+
+/// Turn a recent `workspace` at position `recency` in the list of recent workspaces into an item
+/// to launch.
+///
+/// See [`remote_item`] for details; this function parses the remote of `workspace` itself.
+fn recent_item(
+    workspace: RecentWorkspace,
+    recency: usize,
+    home_dir: Option<&Path>,
+) -> Result<WorkspaceItem> {
+    let remote = Remote::from_url(&workspace.url);
+    remote_item(workspace, remote, recency, home_dir)
+}
+
+/// Turn a recent `workspace` on `remote` at position `recency` into an item to launch.
+///
+/// Use the label of the workspace as name if it has one, and the decoded last segment of its URL
+/// otherwise, followed by the remote of the workspace, if any.  Add the name of the profile of
+/// the workspace, if any.  Describe local paths relative to `home_dir`.
+///
+/// Open remote workspaces with `--folder-uri` or `--file-uri`; VSCode opens an empty window for
+/// remote URIs otherwise.
+fn remote_item(
+    workspace: RecentWorkspace,
+    remote: Option<Remote>,
+    recency: usize,
+    home_dir: Option<&Path>,
+) -> Result<WorkspaceItem> {
+    let RecentWorkspace {
+        url,
+        label,
+        profile,
+        kind,
+        last_opened,
+    } = workspace;
+    let has_label = label.is_some();
+    let name = label
+        .or_else(|| {
+            url.split('/').last().map(|name| {
+                let name = percent_decode(name);
+                // Show multi-root workspaces by the name of their workspace file
+                let name = name.strip_suffix(CODE_WORKSPACE_EXTENSION).unwrap_or(&name);
+                match &remote {
+                    Some(remote) => remote.display_name(name),
+                    None => name.to_string(),
+                }
+            })
+        })
+        .map(|name| match &profile {
+            Some(profile) => format!("{} ({})", name, profile),
+            None => name,
+        });
+    if let Some(name) = name {
+        let path = workspace_path(&url);
+        let segments = path
+            .as_deref()
+            .map_or_else(Vec::new, |path| path_segments(path, home_dir));
+        let suffix = path.as_deref().and_then(path_suffix);
+        let url: Arc<str> = url.into();
+        let target = match (&remote, kind) {
+            (_, RecentKind::File) => WorkspaceTarget::FileUri(url.clone()),
+            (None, _) => WorkspaceTarget::Uri(url.clone()),
+            (Some(_), RecentKind::Folder) => WorkspaceTarget::RemoteFolder(url.clone()),
+            (Some(_), RecentKind::Workspace) => WorkspaceTarget::FileUri(url.clone()),
+        };
+        let remote = remote.is_some();
+        let workspace_file = path
+            .clone()
+            .filter(|_| kind == RecentKind::Workspace && !remote && !has_label);
+        // The local home directory means nothing for paths on remotes
+        let description = path
+            .as_deref()
+            .and_then(|path| describe_path(path, home_dir.filter(|_| !remote)));
+        let item = WorkspaceItem {
+            name,
+            url,
+            target,
+            path,
+            segments,
+            suffix,
+            recency,
+            kind,
+            remote,
+            git_remote: None,
+            description,
+            last_opened,
+            profile,
+            workspace_file,
+            canonical_path: None,
+            on_network_mount: false,
+        };
+        trace!("Found recent workspace item {:?}", item);
+        Ok(item)
+    } else {
+        Err(anyhow!("Failed to extract workspace name from URL {}", url))
+    }
+}
+
+/// The URLs of all `items`, to publish for deduplication.
+///
+/// Publish the URLs from storage rather than the URIs to open, which differ for code-server.
+fn workspace_uris(items: &Workspaces) -> HashSet<Arc<str>> {
+    items.values().map(|item| item.url.clone()).collect()
+}
+
+/// Resolve the canonical path of all local `items`, to find workspaces opened through symlinks.
+///
+/// If `git_remotes` is true also find the `origin` remote of all local git repositories among
+/// `items`, to match search terms against.  Mark workspaces on `network_mounts`, and skip them,
+/// because accessing them may block for a long time.
+///
+/// Remove workspaces whose path resolves to the same directory as a more recent workspace, and
+/// keep the remaining workspaces as they are, to open them with the URI the app knows.
+fn resolve_local_items(items: &mut Workspaces, network_mounts: &NetworkMounts, git_remotes: bool) {
+    for item in items.values_mut() {
+        let path = item.path.as_deref().filter(|_| !item.remote).map(Path::new);
+        item.on_network_mount = path.map_or(false, |path| network_mounts.contains(path));
+        let path = path.filter(|_| !item.on_network_mount);
+        item.canonical_path = path.and_then(|path| std::fs::canonicalize(path).ok());
+        if git_remotes {
+            item.git_remote = path.and_then(GitRemote::origin_of);
+        }
+    }
+    let mut seen = HashSet::new();
+    items.retain(|id, item| match &item.canonical_path {
+        Some(canonical) if !seen.insert(canonical.clone()) => {
+            debug!("Skipping {}, same as a more recent workspace", id);
+            false
+        }
+        _ => true,
+    });
+}
+
+/// A storage backend of VSCode to read recent workspaces from.
+#[derive(Debug, Copy, Clone)]
+enum StorageBackend {
+    /// The `storage.json` file.
+    StorageJson,
+    /// The `state.vscdb` database.
+    StateDb,
+    /// The workspace storage.
+    WorkspaceStorage,
+    /// The `Backups/workspaces.json` file.
+    Backups,
+}
+
+impl StorageBackend {
+    /// Read workspaces from this backend in the given `config_dir`.
+    fn read_workspaces(self, config_dir: &Path) -> Result<Vec<RecentWorkspace>> {
+        match self {
+            StorageBackend::StorageJson => {
+                Storage::from_storage_json(config_dir).map(Storage::into_workspaces)
+            }
+            StorageBackend::StateDb => {
+                Storage::from_state_db(config_dir).map(Storage::into_workspaces)
+            }
+            StorageBackend::WorkspaceStorage => workspace_storage(config_dir),
+            StorageBackend::Backups => {
+                BackupWorkspaces::from_dir(config_dir).map(BackupWorkspaces::into_workspaces)
+            }
+        }
+    }
+}
+
+/// Storage backends with lists of recent workspaces, in order of preference.
+const RECENT_WORKSPACES_BACKENDS: [StorageBackend; 2] =
+    [StorageBackend::StorageJson, StorageBackend::StateDb];
+
+/// Find recent workspaces in the given `config_dir` of a VSCode variant.
+///
+/// Try all backends with lists of recent workspaces in order, and return the workspaces of
+/// the first backend which has any.  Fail only if all backends failed.
+fn find_recent_workspaces(config_dir: &Path) -> Result<Vec<RecentWorkspace>> {
+    let mut errors = Vec::new();
+    for backend in RECENT_WORKSPACES_BACKENDS.iter() {
+        match backend.read_workspaces(config_dir) {
+            Ok(workspaces) if !workspaces.is_empty() => {
+                debug!(
+                    "Found {} workspace(s) in {:?} of {}",
+                    workspaces.len(),
+                    backend,
+                    config_dir.display()
+                );
+                return Ok(workspaces);
+            }
+            Ok(_) => debug!("No workspaces in {:?} of {}", backend, config_dir.display()),
+            Err(error) => {
+                debug!("Skipping {:?}: {:#}", backend, error);
+                errors.push(error);
+            }
+        }
+    }
+    if errors.len() == RECENT_WORKSPACES_BACKENDS.len() {
+        Err(errors.pop().unwrap())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Storage backends which supplement the list of recent workspaces, in order of preference.
+const SUPPLEMENTAL_BACKENDS: [StorageBackend; 2] =
+    [StorageBackend::Backups, StorageBackend::WorkspaceStorage];
+
+/// Merge `workspace` into `workspaces`, where `seen` maps URLs to indexes in `workspaces`.
+///
+/// Add `workspace` if its URL is new; otherwise only take the time it was last opened, if the
+/// existing workspace lacks it.
+fn merge_workspace(
+    workspaces: &mut Vec<RecentWorkspace>,
+    seen: &mut HashMap<String, usize>,
+    workspace: RecentWorkspace,
+) {
+    match seen.get(&workspace.url) {
+        Some(index) => {
+            let existing = &mut workspaces[*index];
+            existing.last_opened = existing.last_opened.or(workspace.last_opened);
+        }
+        None => {
+            seen.insert(workspace.url.clone(), workspaces.len());
+            workspaces.push(workspace);
+        }
+    }
+}
+
+/// Find workspaces in the given `config_dir` of a VSCode variant.
+///
+/// Find recent workspaces, and merge all workspaces from supplemental backends, to include
+/// workspaces which dropped out of the list of recent workspaces, or whose window crashed before
+/// VSCode updated the list.  Fail only if no backend could be read at all.
+fn find_workspaces(config_dir: &Path) -> Result<Vec<RecentWorkspace>> {
+    let (mut workspaces, mut error) = match find_recent_workspaces(config_dir) {
+        Ok(workspaces) => (workspaces, None),
+        Err(error) => (Vec::new(), Some(error)),
+    };
+    let mut seen: HashMap<String, usize> = workspaces
+        .iter()
+        .enumerate()
+        .map(|(index, w)| (w.url.clone(), index))
+        .collect();
+    for backend in SUPPLEMENTAL_BACKENDS.iter() {
+        match backend.read_workspaces(config_dir) {
+            Ok(supplemental) => {
+                // We could read at least one backend, so we don't fail
+                error = None;
+                for workspace in supplemental {
+                    trace!("Merging workspace {} from {:?}", workspace.url, backend);
+                    merge_workspace(&mut workspaces, &mut seen, workspace);
+                }
+            }
+            Err(error) => debug!("Skipping {:?}: {:#}", backend, error),
+        }
+    }
+    for workspace in find_profile_workspaces(config_dir) {
+        trace!(
+            "Merging workspace {} from profile {:?}",
+            workspace.url,
+            workspace.profile
+        );
+        merge_workspace(&mut workspaces, &mut seen, workspace);
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(workspaces),
+    }
+}
+
+/// Find recent workspaces of all user data profiles in the given `config_dir`.
+///
+/// With profiles VSCode may keep state per profile in `User/profiles/<location>/globalStorage`.
+/// Read recent workspaces of each profile and annotate them with the name of the profile, as
+/// stored in `storage.json`.
+fn find_profile_workspaces(config_dir: &Path) -> Vec<RecentWorkspace> {
+    let profiles_dir = config_dir.join("User").join("profiles");
+    let entries = match std::fs::read_dir(&profiles_dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            trace!("No profiles in {}: {}", profiles_dir.display(), error);
+            return Vec::new();
+        }
+    };
+    let profiles = Storage::from_storage_json(config_dir)
+        .map(|storage| storage.user_data_profiles)
+        .unwrap_or_default();
+    // Read the state databases of all profiles concurrently
+    let readers: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let location = entry.file_name().to_string_lossy().into_owned();
+            let name = profiles
+                .iter()
+                .find(|profile| profile.location == location)
+                .map_or(location, |profile| profile.name.clone());
+            let storage_dir = entry.path().join("globalStorage");
+            std::thread::spawn(move || {
+                let storage = Storage::from_state_db_file(storage_dir.join("state.vscdb"))
+                    .or_else(|_| Storage::from_dir(&storage_dir));
+                (name, storage.map(Storage::into_workspaces))
+            })
+        })
+        .collect();
+    let mut workspaces = Vec::new();
+    for reader in readers {
+        match reader.join() {
+            Ok((name, Ok(found))) => {
+                workspaces.extend(found.into_iter().map(|workspace| RecentWorkspace {
+                    profile: Some(name.clone()),
+                    ..workspace
+                }));
+            }
+            Ok((name, Err(error))) => debug!("Skipping profile {}: {:#}", name, error),
+            Err(_) => warn!("Failed to read a profile in {}", profiles_dir.display()),
+        }
+    }
+    workspaces
+}
+
+/// Find workspaces opened on this machine with Remote - SSH.
+///
+/// `server_data_dir` is the data directory of the VSCode server, e.g. `~/.vscode-server/data`,
+/// which has the same layout as the configuration directory of VSCode.  Return all workspaces
+/// whose remote path exists on this machine, as local workspaces.  Skip workspaces on
+/// `network_mounts`, because checking whether they exist may block for a long time.
+fn find_server_workspaces(
+    server_data_dir: &Path,
+    network_mounts: &NetworkMounts,
+) -> Vec<RecentWorkspace> {
+    let workspaces = match find_workspaces(server_data_dir) {
+        Ok(workspaces) => workspaces,
+        Err(error) => {
+            debug!(
+                "Skipping server workspaces in {}: {:#}",
+                server_data_dir.display(),
+                error
+            );
+            return Vec::new();
+        }
+    };
+    workspaces
+        .into_iter()
+        .filter_map(|workspace| {
+            let path = remote_path(&workspace.url)?;
+            let local_path = PathBuf::from(percent_decode(path));
+            if network_mounts.contains(&local_path) {
+                debug!(
+                    "Skipping server workspace {} on network mount",
+                    workspace.url
+                );
+                None
+            } else if local_path.exists() {
+                trace!("Found server workspace {}", workspace.url);
+                Some(RecentWorkspace {
+                    url: format!("file://{}", path),
+                    // The label refers to the remote, so drop it
+                    label: None,
+                    ..workspace
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Storage of recent workspaces of a VSCode variant.
+///
+/// Holds only plain data, to read storage on a background thread.
+#[derive(Debug, Clone)]
+struct WorkspaceStorage {
+    app_id: String,
+    /// Candidates for the configuration directory, in order of preference.
+    config_dirs: Vec<PathBuf>,
+    /// Whether to skip remote workspaces.
+    hide_remote: bool,
+    /// Whether to find the `origin` remote of local git repositories.
+    git_remotes: bool,
+    /// The data directory of the VSCode server on this machine, if any.
+    server_data_dir: Option<PathBuf>,
+    /// The file to persist found workspaces in across restarts, if any.
+    cache_file: Option<PathBuf>,
+}
+
+impl WorkspaceStorage {
+    /// All configuration directories and the server data directory.
+    fn dirs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.config_dirs.iter().chain(&self.server_data_dir)
+    }
+
+    /// All paths to watch for changes of storage.
+    fn paths(&self) -> Vec<PathBuf> {
+        self.dirs().flat_map(|dir| storage_paths(dir)).collect()
+    }
+
+    /// Stamp the storage of all configuration directories and the server data directory.
+    fn stamps(&self) -> Vec<(PathBuf, FileStamp)> {
+        self.dirs().flat_map(|dir| storage_stamps(dir)).collect()
+    }
+
+    /// Read workspaces found by an earlier run from the cache file, if any.
+    fn load_cache_file(&self) -> Option<FoundWorkspaces> {
+        let path = self.cache_file.as_ref()?;
+        match CachedWorkspaces::from_file(path) {
+            Ok(Some(cached)) => {
+                debug!(
+                    "Loaded {} cached workspace(s) for {} from {}",
+                    cached.items.len(),
+                    self.app_id,
+                    path.display()
+                );
+                Some((cached.stamps, Arc::new(cached.items.into_iter().collect())))
+            }
+            Ok(None) => None,
+            Err(error) => {
+                warn!("Ignoring cached workspaces: {:#}", error);
+                None
+            }
+        }
+    }
+
+    /// Persist `items` found in storage with `stamps` to the cache file, if any.
+    fn save_cache_file(&self, stamps: &[(PathBuf, FileStamp)], items: &Workspaces) {
+        if let Some(path) = &self.cache_file {
+            let cached = CachedWorkspaces::new(
+                stamps.to_vec(),
+                items
+                    .iter()
+                    .map(|(id, item)| (id.clone(), item.clone()))
+                    .collect(),
+            );
+            if let Err(error) = cached.save(path) {
+                warn!("Failed to cache workspaces: {:#}", error);
+            }
+        }
+    }
+
+    /// Read all recent workspaces from storage, and persist them to the cache file.
+    ///
+    /// Return the workspaces along with the stamps of the storage they came from.
+    fn read(&self) -> Result<FoundWorkspaces> {
+        let stamps = self.stamps();
+        let mut items = IndexMap::new();
+        info!("Finding recent workspaces for {}", self.app_id);
+        let network_mounts = NetworkMounts::current();
+        let home_dir = dirs::home_dir();
+        let id_prefix = format!("vscode-search-provider-{}-", self.app_id);
+        // Read the server data directory concurrently with the configuration directory
+        let server_workspaces = self.server_data_dir.clone().map(|dir| {
+            let network_mounts = network_mounts.clone();
+            std::thread::spawn(move || find_server_workspaces(&dir, &network_mounts))
+        });
+        let mut workspaces = read_from_first_dir(&self.config_dirs, find_workspaces)?;
+        if let Some(server_workspaces) = server_workspaces {
+            let server_workspaces = server_workspaces.join().unwrap_or_else(|_| {
+                warn!("Failed to read server workspaces of {}", self.app_id);
+                Vec::new()
+            });
+            let mut seen: HashSet<String> = workspaces.iter().map(|w| w.url.clone()).collect();
+            for workspace in server_workspaces {
+                if seen.insert(workspace.url.clone()) {
+                    workspaces.push(workspace);
+                }
+            }
+        }
+        for (recency, workspace) in workspaces.into_iter().enumerate() {
+            trace!("Discovered workspace url {}", workspace.url);
+            let remote = Remote::from_url(&workspace.url);
+            match &remote {
+                Some(_) if self.hide_remote => {
+                    debug!("Skipping remote workspace {}", workspace.url);
+                    continue;
+                }
+                Some(remote) if remote.is_windows_only() => {
+                    debug!("Skipping Windows-only workspace {}", workspace.url);
+                    continue;
+                }
+                _ => {}
+            }
+            let id = workspace_id(&id_prefix, &workspace.url);
+            match remote_item(workspace, remote, recency, home_dir.as_deref()) {
+                Ok(item) => {
+                    items.insert(id, item);
+                }
+                Err(err) => {
+                    warn!("Skipping workspace: {}", err)
+                }
+            }
+        }
+        resolve_local_items(&mut items, &network_mounts, self.git_remotes);
+        info!("Found {} workspace(s) for {}", items.len(), self.app_id);
+        self.save_cache_file(&stamps, &items);
+        Ok((stamps, Arc::new(items)))
+    }
+}
+
+/// Workspaces found in storage, along with the stamps of the storage they came from.
+///
+/// Providers share found workspaces, to avoid copying all workspaces for every search.
+type FoundWorkspaces = (Vec<(PathBuf, FileStamp)>, Arc<Workspaces>);
+
+/// Recent workspaces of VSCode variants.
+///
+/// Read storage on a background thread if possible, to keep the main loop responsive.
+///
+/// Clones share found workspaces, so that finding workspaces with one clone warms up all others.
+///
+/// Publish workspaces for deduplication whenever they're found, including workspaces loaded from
+/// the cache file at startup, so that other providers hide duplicates right from the first search.
+#[derive(Clone)]
+struct VscodeWorkspacesSource {
+    /// The storage to read workspaces from.
+    storage: WorkspaceStorage,
+    /// Publishes found workspaces to hide them in providers of other editors.
+    dedup: Deduplicator,
+    /// Workspaces found last, along with the stamps of the storage they came from.
+    cache: Rc<RefCell<Option<FoundWorkspaces>>>,
+    /// Watches storage for changes since workspaces were found last, if possible.
+    watch: Rc<RefCell<Option<FileWatch>>>,
+    /// Whether a background thread currently reads storage.
+    refreshing: Rc<Cell<bool>>,
+}
+
+impl VscodeWorkspacesSource {
+    /// Create a source for workspaces in `storage`, which publishes workspaces to `dedup`.
+    fn new(storage: WorkspaceStorage, dedup: Deduplicator) -> Self {
+        Self {
+            storage,
+            dedup,
+            cache: Rc::default(),
+            watch: Rc::default(),
+            refreshing: Rc::default(),
+        }
+    }
+
+    /// Load workspaces found by an earlier run from the cache file, unless we found workspaces
+    /// already.
+    fn load_cache_file(&self) {
+        if self.cache.borrow().is_none() {
+            if let Some(found) = self.storage.load_cache_file() {
+                self.dedup.publish(workspace_uris(&found.1));
+                self.cache.replace(Some(found));
+            }
+        }
+    }
+
+    /// Watch storage for changes, from now on.
+    ///
+    /// Watch again before reading, to notice changes while reading.
+    fn watch_storage(&self) {
+        self.watch.replace(FileWatch::new(&self.storage.paths()));
+    }
+
+    /// Whether we can use cached workspaces.
+    ///
+    /// Trust the watch if storage is watched, and otherwise compare `stamps` of storage.
+    fn is_cache_current(&self, stamps: &Option<Vec<(PathBuf, FileStamp)>>) -> bool {
+        match (self.cache.borrow().as_ref(), stamps) {
+            (Some(_), None) => true,
+            (Some((cached_stamps, _)), Some(stamps)) => cached_stamps == stamps,
+            (None, _) => false,
+        }
+    }
+
+    /// Read storage again on a background thread, unless a thread already reads storage.
+    ///
+    /// Update cached workspaces on the main loop when the thread is done.
+    fn refresh_in_background(&self) {
+        if self.refreshing.replace(true) {
+            return;
+        }
+        self.watch_storage();
+        let storage = self.storage.clone();
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        std::thread::spawn(move || {
+            // The receiver only goes away with the main loop, so we can ignore send errors.
+            let _ = sender.send(storage.read());
+        });
+        let cache = self.cache.clone();
+        let watch = self.watch.clone();
+        let refreshing = self.refreshing.clone();
+        let dedup = self.dedup.clone();
+        let app_id = self.storage.app_id.clone();
+        receiver.attach(None, move |result| {
+            refreshing.set(false);
+            match result {
+                Ok(found) => {
+                    dedup.publish(workspace_uris(&found.1));
+                    cache.replace(Some(found));
+                }
+                Err(error) => {
+                    error!(
+                        "Failed to find recent workspaces of {}: {:#}",
+                        app_id, error
+                    );
+                    // Stop trusting the watch, to try again on the next search
+                    watch.replace(None);
+                }
+            }
+            glib::Continue(false)
+        });
+    }
+
+    /// Find workspaces ahead of the first search.
+    ///
+    /// Load workspaces of the last run from the cache file, and read storage in the background.
+    fn warm_up(&self) {
+        self.load_cache_file();
+        self.refresh_in_background();
+    }
+}
+
+impl WorkspacesSource for VscodeWorkspacesSource {
+    /// Find recent workspaces.
+    ///
+    /// Return the workspaces found last if their storage didn't change since, including workspaces
+    /// found by an earlier run.  If storage changed, return the workspaces found last as well, and
+    /// read storage again in the background for the next search.  Only read storage right away if
+    /// we have no workspaces at all yet.
+    fn find_workspaces(&self) -> Result<Arc<Workspaces>> {
+        self.load_cache_file();
+        let watched = self
+            .watch
+            .borrow()
+            .as_ref()
+            .map_or(false, |watch| !watch.has_changed());
+        // Don't touch the file system if we watch storage
+        let stamps = if watched {
+            None
+        } else {
+            Some(self.storage.stamps())
+        };
+        let cached = self.cache.borrow().as_ref().map(|(_, items)| items.clone());
+        match cached {
+            Some(items) if self.is_cache_current(&stamps) => {
+                debug!(
+                    "Storage of {} unchanged, using cached workspaces",
+                    self.storage.app_id
+                );
+                Ok(items)
+            }
+            Some(items) => {
+                debug!(
+                    "Storage of {} changed, using cached workspaces while reading storage",
+                    self.storage.app_id
+                );
+                self.refresh_in_background();
+                Ok(items)
+            }
+            None => {
+                self.watch_storage();
+                let (stamps, items) = self.storage.read()?;
+                self.dedup.publish(workspace_uris(&items));
+                self.cache.replace(Some((stamps, Arc::clone(&items))));
+                Ok(items)
+            }
+        }
+    }
+}
+
+/// Read the address code-server binds to from its `config.yaml` in `config_dir`.
+///
+/// We don't parse YAML fully; code-server writes a flat config file, so we just look for the
+/// `bind-addr` key.  Fall back to the default address if there's no config file or if it
+/// doesn't set `bind-addr`.
+fn code_server_bind_addr<P: AsRef<Path>>(config_dir: P) -> String {
+    let path = config_dir.as_ref().join("config.yaml");
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => parse_code_server_bind_addr(&contents)
+            .unwrap_or(CODE_SERVER_DEFAULT_BIND_ADDR)
+            .to_string(),
+        Err(err) => {
+            warn!("Failed to read {}: {}", path.display(), err);
+            CODE_SERVER_DEFAULT_BIND_ADDR.to_string()
+        }
+    }
+}
+
+/// Extract the `bind-addr` from the contents of a code-server `config.yaml`.
+fn parse_code_server_bind_addr(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .filter_map(|line| line.strip_prefix("bind-addr:"))
+        .map(|value| value.trim().trim_matches('"').trim_matches('\''))
+        .find(|value| !value.is_empty())
+}
+
+/// Turn a workspace URL into a URL which opens the workspace in code-server at `bind_addr`.
+///
+/// Return `None` if the workspace URL doesn't refer to a local folder or workspace file.
+fn code_server_url(bind_addr: &str, workspace_url: &str) -> Option<String> {
+    workspace_url.strip_prefix("file://").map(|path| {
+        let parameter = if path.ends_with(CODE_WORKSPACE_EXTENSION) {
+            "workspace"
+        } else {
+            "folder"
+        };
+        format!("http://{}/?{}={}", bind_addr, parameter, path)
+    })
+}
+
+/// Turn a recent `workspace` at position `recency` into an item to open in code-server at
+/// `bind_addr`, describing local paths relative to `home_dir`.
+///
+/// Fail if the workspace isn't local, because code-server only opens local workspaces.
+fn code_server_item(
+    bind_addr: &str,
+    workspace: RecentWorkspace,
+    recency: usize,
+    home_dir: Option<&Path>,
+) -> Result<WorkspaceItem> {
+    let url = code_server_url(bind_addr, &workspace.url)
+        .with_context(|| format!("Non-local workspace {}", workspace.url))?;
+    recent_item(workspace, recency, home_dir).map(|item| WorkspaceItem {
+        target: WorkspaceTarget::Uri(url.into()),
+        ..item
+    })
+}
+
+/// Recent workspaces of a local code-server installation.
+///
+/// Read workspaces on every search, and publish them for deduplication.
+struct CodeServerWorkspacesSource {
+    /// The data directory of code-server.
+    data_dir: PathBuf,
+    /// The address code-server listens on.
+    bind_addr: String,
+    /// Whether to find the `origin` remote of local git repositories.
+    git_remotes: bool,
+    /// Publishes found workspaces to hide them in providers of other editors.
+    dedup: Deduplicator,
+}
+
+impl WorkspacesSource for CodeServerWorkspacesSource {
+    fn find_workspaces(&self) -> Result<Arc<Workspaces>> {
+        let mut items = IndexMap::new();
+        info!("Finding recent code-server workspaces");
+        let home_dir = dirs::home_dir();
+        let workspaces = find_workspaces(&self.data_dir)?;
+        for (recency, workspace) in workspaces.into_iter().enumerate() {
+            let url = workspace.url.clone();
+            trace!("Discovered workspace url {}", url);
+            if workspace.kind == RecentKind::File {
+                // code-server can't open single files from a URL
+                debug!("Skipping recent file {}", url);
+                continue;
+            }
+            let id = workspace_id("vscode-search-provider-code-server-", &url);
+            match code_server_item(&self.bind_addr, workspace, recency, home_dir.as_deref()) {
+                Ok(item) => {
+                    items.insert(id, item);
+                }
+                Err(err) => {
+                    warn!("Skipping workspace: {:#}", err)
+                }
+            }
+        }
+        resolve_local_items(&mut items, &NetworkMounts::current(), self.git_remotes);
+        self.dedup.publish(workspace_uris(&items));
+        info!("Found {} code-server workspace(s)", items.len());
+        Ok(Arc::new(items))
+    }
+}
+
+/// Recent workspaces of Theia.
+///
+/// Read workspaces on every search, and publish them for deduplication.
+struct TheiaWorkspacesSource {
+    /// Candidates for the configuration directory, in order of preference.
+    config_dirs: Vec<PathBuf>,
+    /// Whether to find the `origin` remote of local git repositories.
+    git_remotes: bool,
+    /// Publishes found workspaces to hide them in providers of other editors.
+    dedup: Deduplicator,
+}
+
+impl WorkspacesSource for TheiaWorkspacesSource {
+    fn find_workspaces(&self) -> Result<Arc<Workspaces>> {
+        let mut items = IndexMap::new();
+        info!("Finding recent Theia workspaces");
+        let home_dir = dirs::home_dir();
+        let urls = read_from_first_dir(&self.config_dirs, |dir| {
+            TheiaRecentWorkspaces::from_dir(dir)
+        })?
+        .recent_roots;
+        for (recency, url) in urls.into_iter().enumerate() {
+            trace!("Discovered workspace url {}", url);
+            let id = workspace_id("vscode-search-provider-theia-", &url);
+            match recent_item(RecentWorkspace::new(url), recency, home_dir.as_deref()) {
+                Ok(item) => {
+                    items.insert(id, item);
+                }
+                Err(err) => {
+                    warn!("Skipping workspace: {}", err)
+                }
+            }
+        }
+        resolve_local_items(&mut items, &NetworkMounts::current(), self.git_remotes);
+        self.dedup.publish(workspace_uris(&items));
+        info!("Found {} Theia workspace(s)", items.len());
+        Ok(Arc::new(items))
+    }
+}
+
+/// Load the launch history of the provider with the given `desktop_id` from `state_dir`.
+///
+/// Log an error and return an empty history if the history is invalid.
+fn load_launch_history(state_dir: &Path, desktop_id: &str) -> LaunchHistory {
+    let path = state_dir
+        .join("history")
+        .join(format!("{}.json", desktop_id));
+    LaunchHistory::from_file(path).unwrap_or_else(|error| {
+        error!("Failed to load launch history: {:#}", error);
+        LaunchHistory::default()
+    })
+}
+
+/// Load general settings from the configuration directory of this service.
+///
+/// `user_config_dir` is the configuration directory of the current user.  Log an error and
+/// return default settings if the settings are invalid.
+fn load_settings(user_config_dir: &Path) -> Settings {
+    Settings::from_dir(user_config_dir.join(env!("CARGO_PKG_NAME"))).unwrap_or_else(|error| {
+        error!("Failed to load settings: {:#}", error);
+        Settings::default()
+    })
+}
+
+/// Load providers defined by the user in the configuration directory of this service.
+///
+/// `user_config_dir` is the configuration directory of the current user.  Log an error and
+/// return no providers if the configuration is invalid.
+fn load_user_providers(user_config_dir: &Path) -> ProvidersConfig {
+    ProvidersConfig::from_dir(user_config_dir.join(env!("CARGO_PKG_NAME"))).unwrap_or_else(
+        |error| {
+            error!("Failed to load providers defined by user: {:#}", error);
+            ProvidersConfig::default()
+        },
+    )
+}
+
+/// Load providers defined by the user, and optionally `discover` further providers.
+///
+/// Discovered providers are returned as if the user had defined them.
+fn load_additional_providers(user_config_dir: &Path, discover: bool) -> ProvidersConfig {
+    let mut config = load_user_providers(user_config_dir);
+    if discover {
+        let known_desktop_ids: Vec<&str> = all_providers()
+            .flat_map(|p| p.desktop_ids.iter().copied())
+            .chain(config.providers.iter().map(|p| p.desktop_id.as_str()))
+            .collect();
+        let discovered = discover_providers(user_config_dir, &known_desktop_ids);
+        config.providers.extend(discovered);
+    }
+    config
+}
+
+/// Get the configuration location of a provider defined by the user.
+///
+/// `dirnames` must contain the configuration directory of `provider` with `~/` stripped.
+fn user_config_location<'a>(
+    provider: &UserProvider,
+    dirnames: &'a [&'a str],
+) -> ConfigLocation<'a> {
+    if provider.config_dir.starts_with("~/") {
+        ConfigLocation::Home { dirnames }
+    } else {
+        ConfigLocation::User { dirnames }
+    }
+}
+
+/// The directory which has the desktop files of installed snaps.
+const SNAPD_DATA_DIR: &str = "/var/lib/snapd/desktop";
+
+/// Add `SNAPD_DATA_DIR` to the `data_dirs` from `$XDG_DATA_DIRS`.
+///
+/// Return `None` if `data_dirs` already include `SNAPD_DATA_DIR`.
+fn data_dirs_with_snapd(data_dirs: Option<&str>) -> Option<String> {
+    let data_dirs = data_dirs
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or("/usr/local/share:/usr/share");
+    if data_dirs.split(':').any(|dir| dir == SNAPD_DATA_DIR) {
+        None
+    } else {
+        Some(format!("{}:{}", data_dirs, SNAPD_DATA_DIR))
+    }
+}
+
+/// Make Gio find desktop files of installed snaps.
+///
+/// snapd adds its data directory to `$XDG_DATA_DIRS` only in login shells, so systemd user
+/// services often don't see the desktop files of snaps, and can't find or launch apps from snaps.
+/// Must be called before the first use of Gio, which caches data directories.
+fn add_snapd_data_dir() {
+    if Path::new(SNAPD_DATA_DIR).is_dir() {
+        let current = std::env::var("XDG_DATA_DIRS").ok();
+        if let Some(data_dirs) = data_dirs_with_snapd(current.as_deref()) {
+            std::env::set_var("XDG_DATA_DIRS", data_dirs);
+        }
+    }
+}
+
+/// The name to request on the bus.
+const BUSNAME: &str = "de.swsnr.searchprovider.VSCode";
+
+/// The app of a provider which we considered for registration.
+#[derive(Debug)]
+struct ProviderApp {
+    /// The desktop IDs of the app, in order of preference.
+    desktop_ids: Vec<String>,
+    /// The desktop ID we registered the provider for, or `None` if the app wasn't installed.
+    registered: Option<String>,
+}
+
+impl ProviderApp {
+    /// Consider `provider` for registration with the app we found for it, if any.
+    fn new(provider: &ProviderDefinition, registered: Option<&str>) -> Self {
+        Self {
+            desktop_ids: provider
+                .desktop_ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect(),
+            registered: registered.map(str::to_string),
+        }
+    }
+
+    /// Whether we'd register this provider for another desktop ID now, or not at all, according
+    /// to the currently installed `apps`.
+    fn has_changed(&self, apps: &AppRegistry) -> bool {
+        let installed = self.desktop_ids.iter().find(|id| apps.lookup(id).is_some());
+        installed != self.registered.as_ref()
+    }
+}
+
+/// Registered search providers.
+struct Registration {
+    /// The sources of registered providers which benefit from finding workspaces early.
+    warm_up_sources: Vec<VscodeWorkspacesSource>,
+    /// The object paths of all registered providers.
+    object_paths: Vec<String>,
+    /// The apps of all providers we considered for registration.
+    apps: Vec<ProviderApp>,
+    /// The desktop IDs of all providers defined by the user or discovered.
+    additional_desktop_ids: Vec<String>,
+    /// How long to wait for requests before quitting, from settings.
+    idle_timeout: Option<Duration>,
+}
+
+/// Register all search providers whose app is installed, looking up apps in `apps`.
+///
+/// If `discover` is true, also discover VSCode variants among installed apps, and register
+/// providers for these.  `max_results` overrides the maximum number of results from settings.
+/// All providers record requests in `activity`, and cancel searches superseded according to
+/// `searches`.
+fn register_search_providers(
+    connection: &zbus::Connection,
+    object_server: &mut zbus::ObjectServer,
+    apps: &AppRegistry,
+    activity: &Activity,
+    searches: &Searches,
+    discover: bool,
+    max_results: Option<usize>,
+) -> Result<Registration> {
+    let mut warm_up_sources = Vec::new();
+    let mut object_paths = Vec::new();
+    let mut provider_apps = Vec::new();
+    let user_config_dir =
+        dirs::config_dir().with_context(|| "No configuration directory for current user!")?;
+    let home_dir = dirs::home_dir().with_context(|| "No home directory for current user!")?;
+    let launch_context = create_launch_context(
+        connection.clone(),
+        SystemdScopeSettings {
+            prefix: concat!("app-", env!("CARGO_BIN_NAME")).to_string(),
+            started_by: env!("CARGO_BIN_NAME").to_string(),
+            documentation: vec![env!("CARGO_PKG_HOMEPAGE").to_string()],
+        },
+    );
+    let session_environment =
+        SessionEnvironment::new(connection.inner().clone(), launch_context.clone());
+    session_environment.refresh();
+
+    let mut settings = load_settings(&user_config_dir);
+    settings.max_results = max_results.or(settings.max_results);
+    let idle_timeout = settings
+        .idle_timeout
+        .map(|minutes| Duration::from_secs(minutes * 60));
+    let state_dir = state_dir(&home_dir);
+    // Rank providers in order of registration, with the preferred editor first
+    let shared_workspaces = SharedWorkspaces::default();
+    let mut next_rank = 0;
+    let mut deduplicator = |desktop_id: &str| {
+        next_rank += 1;
+        let preferred = settings.preferred_editor.as_deref() == Some(desktop_id);
+        shared_workspaces.deduplicator(if preferred { 0 } else { next_rank })
+    };
+    let user_providers = load_additional_providers(&user_config_dir, discover);
+    let additional_desktop_ids = user_providers.desktop_ids();
+    let user_names: Vec<([&str; 1], [&str; 1])> = user_providers
+        .providers
+        .iter()
+        .map(|provider| {
+            let config_dir = provider.config_dir.as_str();
+            (
+                [provider.desktop_id.as_str()],
+                [config_dir.strip_prefix("~/").unwrap_or(config_dir)],
+            )
+        })
+        .collect();
+    let user_definitions: Vec<ProviderDefinition> = user_providers
+        .providers
+        .iter()
+        .zip(&user_names)
+        .map(|(provider, (desktop_ids, dirnames))| ProviderDefinition {
+            label: &provider.label,
+            desktop_ids,
+            relative_obj_path: &provider.object_path,
+            data_folder_name: None,
+            config: user_config_location(provider, dirnames),
+        })
+        .collect();
+
+    for provider in PROVIDERS.iter().chain(&user_definitions) {
+        let found = provider.find_app(apps);
+        provider_apps.push(ProviderApp::new(
+            provider,
+            found.as_ref().map(|(id, _)| *id),
+        ));
+        if let Some((desktop_id, app)) = found {
+            let objpath = provider.objpath(desktop_id);
+            info!(
+                "Registering provider for {} ({}) at {}",
+                provider.label_for(&app),
+                desktop_id,
+                objpath
+            );
+            let mut config_dirs =
+                find_custom_user_data_dirs(&app, &home_dir, provider.data_folder_name);
+            config_dirs.extend(find_portable_config_dirs(&app));
+            config_dirs.extend(provider.config.resolve(&user_config_dir, &home_dir));
+            let editor_settings = config_dirs
+                .iter()
+                .map(|dir| dir.join("User").join("settings.json"))
+                .find(|path| path.is_file());
+            let dedup = deduplicator(desktop_id);
+            let source = VscodeWorkspacesSource::new(
+                WorkspaceStorage {
+                    app_id: desktop_id.to_string(),
+                    config_dirs,
+                    hide_remote: settings.hide_remote,
+                    git_remotes: settings.match_git_remote,
+                    server_data_dir: provider
+                        .data_folder_name
+                        .filter(|_| settings.index_vscode_server)
+                        .map(|name| home_dir.join(format!("{}-server", name)).join("data"))
+                        .filter(|dir| dir.is_dir()),
+                    cache_file: Some(
+                        cache_dir(&home_dir)
+                            .join("workspaces")
+                            .join(format!("{}.json", desktop_id)),
+                    ),
+                },
+                dedup.clone(),
+            );
+            warm_up_sources.push(source.clone());
+            let dbus_provider = WorkspaceSearchProvider::new(
+                app,
+                source,
+                launch_context.clone(),
+                settings.clone(),
+                load_launch_history(&state_dir, desktop_id),
+                dedup,
+            )
+            .with_editor_settings(editor_settings)
+            .with_activity(activity.clone())
+            .with_search_generation(searches.generation(objpath.as_str()))
+            .with_session_environment(session_environment.clone());
+            object_server.at(objpath.as_str(), dbus_provider)?;
+            object_paths.push(objpath);
+        }
+    }
+
+    let code_server_data_dir = dirs::data_dir()
+        .with_context(|| "No data directory for current user!")?
+        .join("code-server");
+    if code_server_data_dir.is_dir() {
+        let found = CODE_SERVER.find_app(apps);
+        provider_apps.push(ProviderApp::new(
+            &CODE_SERVER,
+            found.as_ref().map(|(id, _)| *id),
+        ));
+        if let Some((desktop_id, app)) = found {
+            let objpath = CODE_SERVER.objpath(desktop_id);
+            info!("Registering provider for code-server at {}", objpath);
+            let dedup = deduplicator(desktop_id);
+            let source = CodeServerWorkspacesSource {
+                data_dir: code_server_data_dir,
+                bind_addr: CODE_SERVER
+                    .config
+                    .resolve(&user_config_dir, &home_dir)
+                    .into_iter()
+                    .find(|dir| dir.join("config.yaml").is_file())
+                    .map_or_else(
+                        || CODE_SERVER_DEFAULT_BIND_ADDR.to_string(),
+                        code_server_bind_addr,
+                    ),
+                git_remotes: settings.match_git_remote,
+                dedup: dedup.clone(),
+            };
+            // Publish workspaces for deduplication before the first search
+            if let Err(error) = source.find_workspaces() {
+                warn!("Failed to find recent code-server workspaces: {:#}", error);
+            }
+            let dbus_provider = WorkspaceSearchProvider::new(
+                app,
+                source,
+                launch_context.clone(),
+                // code-server opens workspaces in the browser, without window flags, and can't
+                // open cloned repositories
+                Settings {
+                    clone_dir: None,
+                    ..settings.clone().without_window_modes()
+                },
+                load_launch_history(&state_dir, desktop_id),
+                dedup,
+            )
+            .with_activity(activity.clone())
+            .with_search_generation(searches.generation(objpath.as_str()))
+            .with_session_environment(session_environment.clone());
+            object_server.at(objpath.as_str(), dbus_provider)?;
+            object_paths.push(objpath);
+        }
+    }
+
+    let found = THEIA.find_app(apps);
+    provider_apps.push(ProviderApp::new(&THEIA, found.as_ref().map(|(id, _)| *id)));
+    if let Some((desktop_id, app)) = found {
+        let objpath = THEIA.objpath(desktop_id);
+        info!("Registering provider for {} at {}", desktop_id, objpath);
+        // Rank before moving settings into the provider
+        let dedup = deduplicator(desktop_id);
+        let source = TheiaWorkspacesSource {
+            config_dirs: THEIA.config.resolve(&user_config_dir, &home_dir),
+            git_remotes: settings.match_git_remote,
+            dedup: dedup.clone(),
+        };
+        // Publish workspaces for deduplication before the first search
+        if let Err(error) = source.find_workspaces() {
+            warn!("Failed to find recent Theia workspaces: {:#}", error);
+        }
+        let dbus_provider = WorkspaceSearchProvider::new(
+            app,
+            source,
+            launch_context,
+            // Theia has no window flags
+            settings.without_window_modes(),
+            load_launch_history(&state_dir, desktop_id),
+            dedup,
+        )
+        .with_activity(activity.clone())
+        .with_search_generation(searches.generation(objpath.as_str()))
+        .with_session_environment(session_environment);
+        object_server.at(objpath.as_str(), dbus_provider)?;
+        object_paths.push(objpath);
+    }
+    Ok(Registration {
+        warm_up_sources,
+        object_paths,
+        apps: provider_apps,
+        additional_desktop_ids,
+        idle_timeout,
+    })
+}
+
+/// The search providers of the running service.
+///
+/// Share the object server with [`serve`], to register providers again while the service runs.
+struct Service {
+    /// The connection to the session bus.
+    connection: zbus::Connection,
+    /// The object server which has all providers.
+    object_server: Rc<RefCell<zbus::ObjectServer>>,
+    /// Installed apps.
+    apps: AppRegistry,
+    /// Records requests of all providers.
+    activity: Activity,
+    /// Searches of all providers as they arrive, to cancel superseded searches.
+    searches: Searches,
+    /// Whether to discover VSCode variants among installed apps.
+    discover: bool,
+    /// The maximum number of results from the command line, if any.
+    max_results: Option<usize>,
+    /// The object paths of registered providers.
+    object_paths: RefCell<Vec<String>>,
+    /// The apps of all providers we considered for registration.
+    provider_apps: RefCell<Vec<ProviderApp>>,
+    /// The desktop IDs of all providers defined by the user or discovered.
+    additional_desktop_ids: RefCell<Vec<String>>,
+    /// How long to wait for requests before quitting, from settings.
+    idle_timeout: Rc<Cell<Option<Duration>>>,
+}
+
+impl Service {
+    /// Create a service for providers on `connection`, looking up apps in `apps`.
+    fn new(
+        connection: zbus::Connection,
+        apps: AppRegistry,
+        activity: Activity,
+        discover: bool,
+        max_results: Option<usize>,
+    ) -> Self {
+        let object_server = Rc::new(RefCell::new(zbus::ObjectServer::new(&connection)));
+        Self {
+            connection,
+            object_server,
+            apps,
+            activity,
+            searches: Searches::default(),
+            discover,
+            max_results,
+            object_paths: RefCell::default(),
+            provider_apps: RefCell::default(),
+            additional_desktop_ids: RefCell::default(),
+            idle_timeout: Rc::default(),
+        }
+    }
+
+    /// Register all search providers whose app is installed, with current settings.
+    ///
+    /// Return the sources of registered providers to warm up.
+    fn register(&self) -> Result<Vec<VscodeWorkspacesSource>> {
+        let registration = register_search_providers(
+            &self.connection,
+            &mut self.object_server.borrow_mut(),
+            &self.apps,
+            &self.activity,
+            &self.searches,
+            self.discover,
+            self.max_results,
+        )?;
+        self.object_paths.replace(registration.object_paths);
+        self.provider_apps.replace(registration.apps);
+        self.additional_desktop_ids
+            .replace(registration.additional_desktop_ids);
+        self.idle_timeout.set(registration.idle_timeout);
+        Ok(registration.warm_up_sources)
+    }
+
+    /// Remove all registered providers from the object server.
+    fn unregister(&self) {
+        let mut object_server = self.object_server.borrow_mut();
+        for path in self.object_paths.take() {
+            debug!("Removing provider at {}", path);
+            if let Err(error) = object_server.remove::<WorkspaceSearchProvider, _>(path.as_str()) {
+                error!("Failed to remove provider at {}: {}", path, error);
+            }
+        }
+    }
+
+    /// Remove all providers, and register providers for the currently installed apps again.
+    ///
+    /// Read settings again, but keep the bus name and the connection, so Gnome Shell can search
+    /// right away, and find workspaces of the new providers in the background.
+    fn register_again(&self) -> Result<()> {
+        self.unregister();
+        let sources = self.register()?;
+        info!(
+            "Registered {} provider(s) again",
+            self.object_paths.borrow().len()
+        );
+        warm_up(sources);
+        Ok(())
+    }
+
+    /// Whether we'd register other providers according to the currently installed apps.
+    ///
+    /// If we discover providers also discover them again, to find VSCode variants which were
+    /// installed or removed.
+    fn providers_changed(&self) -> bool {
+        let apps_changed = self
+            .provider_apps
+            .borrow()
+            .iter()
+            .any(|app| app.has_changed(&self.apps));
+        apps_changed || (self.discover && self.discovered_changed())
+    }
+
+    /// Whether discovery finds other providers than at registration.
+    fn discovered_changed(&self) -> bool {
+        match dirs::config_dir() {
+            Some(user_config_dir) => {
+                let desktop_ids = load_additional_providers(&user_config_dir, true).desktop_ids();
+                desktop_ids != *self.additional_desktop_ids.borrow()
+            }
+            None => false,
+        }
+    }
+}
+
+/// The object path of the provider which `message` asks to search, if it's a search.
+fn search_object_path(message: &zbus::Message) -> Option<String> {
+    let header = message.header().ok()?;
+    let member = header.member().ok()??.to_string();
+    if member == "GetInitialResultSet" || member == "GetSubsearchResultSet" {
+        header.path().ok()?.map(|path| path.to_string())
+    } else {
+        None
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct StorageOpenedPathsList {
-    /// Up to code 1.54
-    workspaces3: Option<Vec<String>>,
-    /// From code 1.55
-    entries: Option<Vec<StorageOpenedPathsListEntry>>,
+/// The object path of the provider which `message` asks to search, along with the serial number
+/// of `message`, if it's a search.
+fn search_serial(message: &zbus::Message) -> Option<(String, u32)> {
+    let path = search_object_path(message)?;
+    let serial = message.primary_header().ok()?.serial_num().copied()?;
+    Some((path, serial))
 }
 
-#[derive(Debug, Deserialize)]
-struct Storage {
-    #[serde(rename = "openedPathsList")]
-    opened_paths_list: Option<StorageOpenedPathsList>,
+/// Whether a search in `waiting` messages supersedes `message`.
+///
+/// A search supersedes all earlier searches for the same provider.
+fn is_superseded(
+    message: &zbus::Message,
+    waiting: &VecDeque<zbus::Result<Arc<zbus::Message>>>,
+) -> bool {
+    search_object_path(message).map_or(false, |path| {
+        waiting.iter().any(|next| match next {
+            Ok(next) => search_object_path(next).as_ref() == Some(&path),
+            Err(_) => false,
+        })
+    })
 }
 
-impl Storage {
-    /// Read a VSCode storage.json from the given `reader`.
-    fn read<R: Read>(reader: R) -> Result<Self> {
-        serde_json::from_reader(reader).map_err(Into::into)
-    }
+/// Record all searches on `connection` in `searches` as they arrive, until the connection closes.
+///
+/// Watch the connection on a separate thread, because the main loop blocks while a provider
+/// searches, to cancel searches which a newer search superseded while they run.
+fn record_searches(mut connection: zbus::azync::Connection, searches: Searches) {
+    std::thread::spawn(move || {
+        glib::MainContext::new().block_on(async move {
+            while let Some(result) = connection.next().await {
+                if let Some((path, serial)) = result.ok().and_then(|m| search_serial(&m)) {
+                    trace!("Received search {} for {}", serial, path);
+                    searches.received(&path, serial);
+                }
+            }
+        })
+    });
+}
 
-    /// Read the `storage.json` file in the given `config_dir`.
-    fn from_dir<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
-        let path = config_dir.as_ref().join("storage.json");
-        trace!("Reading storage from {}", path.display());
-        Self::read(
-            File::open(&path)
-                .with_context(|| format!("Failed to open {} for reading", path.display()))?,
-        )
-        .with_context(|| format!("Failed to parse storage from {}", path.display()))
-    }
-
-    /// Move this storage into workspace URLs.
-    fn into_workspace_urls(self) -> Vec<String> {
-        trace!("Extracting workspace URLs from {:?}", self);
-        if let Some(paths) = self.opened_paths_list {
-            let entries = paths.entries.unwrap_or_default();
-            let workspaces3 = paths.workspaces3.unwrap_or_default();
-            entries
-                .into_iter()
-                .filter_map(|entry| entry.folder_uri)
-                .chain(workspaces3.into_iter())
-                .collect()
-        } else {
-            Vec::new()
+/// Dispatch messages on the connection of `service` to its object server, until the connection
+/// closes.
+///
+/// Only borrow the object server for each message, so that the service can add and remove
+/// providers between messages.
+///
+/// Gnome Shell sends a search for every keystroke, and drops the answer to a search once it sends
+/// a newer one.  Before each message take all messages which arrived meanwhile, and answer
+/// searches with no results right away if a newer search for the same provider is waiting, to
+/// only search for the latest terms.  Record every search before dispatching it, for providers
+/// to stop searching once a newer search arrives while they search.
+async fn serve(service: Rc<Service>) {
+    let mut connection = service.connection.inner().clone();
+    record_searches(connection.clone(), service.searches.clone());
+    let mut queue = VecDeque::new();
+    loop {
+        if queue.is_empty() {
+            match connection.next().await {
+                Some(result) => queue.push_back(result),
+                None => break,
+            }
+        }
+        while let Some(Some(result)) = connection.next().now_or_never() {
+            queue.push_back(result);
+        }
+        let message = match queue.pop_front() {
+            Some(Ok(message)) => message,
+            Some(Err(error)) => {
+                error!("Failed to receive message: {}", error);
+                continue;
+            }
+            None => continue,
+        };
+        if is_superseded(&message, &queue) {
+            debug!(
+                "Skipping search superseded by a newer search: {:?}",
+                message
+            );
+            if let Err(error) = service.connection.reply(&message, &Vec::<String>::new()) {
+                error!("Failed to reply to superseded search: {}", error);
+            }
+            continue;
+        }
+        if let Some((path, serial)) = search_serial(&message) {
+            service.searches.started(&path, serial);
+        }
+        match service
+            .object_server
+            .borrow_mut()
+            .dispatch_message(&message)
+        {
+            Ok(true) => trace!("Dispatched message {:?}", message),
+            Ok(false) => trace!("No object for message {:?}", message),
+            Err(error) => error!("Failed to dispatch message {:?}: {}", message, error),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-struct ConfigLocation<'a> {
-    dirname: &'a str,
-}
-
-/// A search provider to expose from this service.
-struct ProviderDefinition<'a> {
-    /// A human readable label for this provider.
-    label: &'a str,
-    /// The ID (that is, the filename) of the desktop file of the corresponding app.
-    desktop_id: &'a str,
-    /// The relative object path to expose this provider at.
-    relative_obj_path: &'a str,
-    /// The location of the configuration for this app.
-    config: ConfigLocation<'a>,
+/// Register providers of `service` again when installed apps change such that we'd register
+/// other providers.
+fn register_again_when_providers_change(service: Rc<Service>) {
+    let apps = service.apps.clone();
+    apps.connect_changed(move || {
+        if service.providers_changed() {
+            info!("Installed editors changed, registering providers again");
+            if let Err(error) = service.register_again() {
+                error!("Failed to register providers again: {:#}", error);
+            }
+        }
+    });
 }
 
-impl ProviderDefinition<'_> {
-    /// Gets the full object path for this provider.
-    fn objpath(&self) -> String {
-        format!("/de/swsnr/searchprovider/vscode/{}", self.relative_obj_path)
+/// Find workspaces of all `sources` in the background, ahead of the first search.
+fn warm_up(sources: Vec<VscodeWorkspacesSource>) {
+    for source in sources {
+        debug!("Warming up workspaces of {}", source.storage.app_id);
+        source.warm_up();
     }
 }
 
-/// Known search providers.
-///
-/// For each definition in this array a corresponding provider file must exist in
-/// `providers/`; the file must refer to the same `desktop_id` and the same object path.
-/// The object path must be unique for each desktop ID, to ensure that this service always
-/// launches the right application associated with the search provider.
-const PROVIDERS: &[ProviderDefinition] = &[
-    // The standard Arch Linux code package from community
-    ProviderDefinition {
-        label: "Code OSS (Arch Linux)",
-        desktop_id: "code-oss.desktop",
-        relative_obj_path: "arch/codeoss",
-        config: ConfigLocation {
-            dirname: "Code - OSS",
-        },
-    },
-    // The binary AUR package for visual studio code: https://aur.archlinux.org/packages/visual-studio-code-bin/
-    ProviderDefinition {
-        label: "Visual Studio Code (AUR package)",
-        desktop_id: "visual-studio-code.desktop",
-        relative_obj_path: "aur/visualstudiocode",
-        config: ConfigLocation { dirname: "Code" },
-    },
-    // The binary AUR package for vscodium: https://aur.archlinux.org/packages/vscodium-bin
-    // and likely other VSCodium packages as well, the Desktop file seems to be fairly standard
-    ProviderDefinition {
-        label: "VSCodium",
-        desktop_id: "VSCodium.desktop",
-        relative_obj_path: "vscodium",
-        config: ConfigLocation {
-            dirname: "VSCodium",
-        },
-    },
-];
+/// The exit code if another process owns `BUSNAME`.
+const EXIT_NAME_TAKEN: i32 = 2;
 
-/// A recent workspace of a VSCode variant.
-#[derive(Debug, PartialEq)]
-struct RecentWorkspace {
-    /// The human readable nfame.
-    name: String,
-    /// The workspace URL.
-    url: String,
+/// Another process owns `BUSNAME`.
+#[derive(Debug)]
+struct NameTaken {
+    /// The ID of the owning process, if known.
+    owner_pid: Option<u32>,
 }
 
-fn recent_item(url: String) -> Result<AppLaunchItem> {
-    if let Some(name) = url.split('/').last() {
-        let item = AppLaunchItem {
-            name: name.to_string(),
-            target: AppLaunchTarget::Uri(url),
-        };
-        trace!("Found recent workspace item {:?}", item);
-        Ok(item)
-    } else {
-        Err(anyhow!("Failed to extract workspace name from URL {}", url))
+impl std::fmt::Display for NameTaken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.owner_pid {
+            Some(pid) => write!(f, "Process {} already owns {}", pid, BUSNAME)?,
+            None => write!(f, "Another process already owns {}", BUSNAME)?,
+        }
+        write!(f, "; use --replace to take over")
     }
 }
 
-struct VscodeWorkspacesSource {
-    app_id: String,
-    /// The configuration directory.
-    config_dir: PathBuf,
-}
-
-impl ItemsSource<AppLaunchItem> for VscodeWorkspacesSource {
-    type Err = Error;
+impl std::error::Error for NameTaken {}
 
-    fn find_recent_items(&self) -> Result<IdMap<AppLaunchItem>, Self::Err> {
-        let mut items = IndexMap::new();
-        info!("Finding recent workspaces for {}", self.app_id);
-        let urls = Storage::from_dir(&self.config_dir)?.into_workspace_urls();
-        for url in urls {
-            trace!("Discovered workspace url {}", url);
-            let id = format!("vscode-search-provider-{}-{}", self.app_id, &url);
-            match recent_item(url) {
-                Ok(item) => {
-                    items.insert(id, item);
-                }
-                Err(err) => {
-                    warn!("Skipping workspace: {}", err)
+/// Request `BUSNAME` on `connection`, and let later instances replace us.
+///
+/// If `replace` is true replace the current owner of `BUSNAME`, if it lets us.  Fail with
+/// [`NameTaken`] if another process keeps `BUSNAME`.
+fn request_name(connection: &zbus::Connection, replace: bool) -> Result<()> {
+    let dbus = zbus::fdo::DBusProxy::new(connection)?;
+    let flags = if replace {
+        RequestNameFlags::AllowReplacement
+            | RequestNameFlags::ReplaceExisting
+            | RequestNameFlags::DoNotQueue
+    } else {
+        RequestNameFlags::AllowReplacement | RequestNameFlags::DoNotQueue
+    };
+    let name = WellKnownName::try_from(BUSNAME).unwrap();
+    match dbus.request_name(name.clone(), flags)? {
+        RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => Ok(()),
+        RequestNameReply::Exists => {
+            let owner_pid = match dbus.get_connection_unix_process_id(name.into()) {
+                Ok(pid) => Some(pid),
+                Err(error) => {
+                    debug!("Failed to get process of owner of {}: {}", BUSNAME, error);
+                    None
                 }
-            }
+            };
+            Err(NameTaken { owner_pid }.into())
         }
-        info!("Found {} workspace(s) for {}", items.len(), self.app_id);
-        Ok(items)
+        reply => Err(anyhow!("Name not acquired: {:?}", reply)),
     }
 }
 
-/// The name to request on the bus.
-const BUSNAME: &str = "de.swsnr.searchprovider.VSCode";
-
-fn register_search_providers(
-    connection: &zbus::Connection,
-    object_server: &mut zbus::ObjectServer,
-) -> Result<()> {
-    let user_config_dir =
-        dirs::config_dir().with_context(|| "No configuration directory for current user!")?;
-    let launch_context = create_launch_context(
-        connection.clone(),
-        SystemdScopeSettings {
-            prefix: concat!("app-", env!("CARGO_BIN_NAME")).to_string(),
-            started_by: env!("CARGO_BIN_NAME").to_string(),
-            documentation: vec![env!("CARGO_PKG_HOMEPAGE").to_string()],
+/// Quit `mainloop` when another instance replaces the instance with `unique_name` as owner of
+/// `BUSNAME`.
+fn quit_when_replaced(mainloop: glib::MainLoop, unique_name: String) {
+    let quit = mainloop.clone();
+    gio::bus_watch_name(
+        gio::BusType::Session,
+        BUSNAME,
+        gio::BusNameWatcherFlags::NONE,
+        move |_, _, owner| {
+            if owner != unique_name {
+                info!("{} replaced us as owner of {}, quitting", owner, BUSNAME);
+                mainloop.quit();
+            }
+        },
+        move |_, _| {
+            info!("Lost {}, quitting", BUSNAME);
+            quit.quit();
         },
     );
+}
 
-    for provider in PROVIDERS {
-        if let Some(app) = gio::DesktopAppInfo::new(provider.desktop_id) {
-            info!(
-                "Registering provider for {} at {}",
-                provider.desktop_id,
-                provider.objpath()
-            );
-            let dbus_provider = AppItemSearchProvider::new(
-                app,
-                VscodeWorkspacesSource {
-                    app_id: provider.desktop_id.to_string(),
-                    config_dir: user_config_dir.join(provider.config.dirname),
-                },
-                launch_context.clone(),
-            );
-            object_server.at(provider.objpath().as_str(), dbus_provider)?;
+/// The number of `SIGHUP` on Linux.
+const SIGHUP: i32 = 1;
+
+/// Register providers of `service` again on `SIGHUP`, to reload settings and providers.
+fn register_again_on_sighup(service: Rc<Service>) {
+    glib::unix_signal_add_local(SIGHUP, move || {
+        info!("Received SIGHUP, reloading settings and providers");
+        if let Err(error) = service.register_again() {
+            error!("Failed to register providers again: {:#}", error);
         }
-    }
-    Ok(())
+        glib::Continue(true)
+    });
 }
 
 /// Starts the DBUS service loop.
 ///
-/// Register all providers whose underlying app is installed.
-fn start_dbus_service() -> Result<()> {
+/// Register all providers whose underlying app is installed, and optionally `discover` further
+/// providers.  Return at most `max_results` for each search, if given.  If `replace` is true,
+/// replace a running instance of this service.
+fn start_dbus_service(discover: bool, max_results: Option<usize>, replace: bool) -> Result<()> {
     let mainloop = create_main_loop();
     let context = glib::MainContext::ref_thread_default();
 
     let connection =
         zbus::Connection::session().with_context(|| "Failed to connect to session bus")?;
 
-    let mut object_server = zbus::ObjectServer::new(&connection);
-    register_search_providers(&connection, &mut object_server)?;
+    let service = Rc::new(Service::new(
+        connection.clone(),
+        AppRegistry::new(),
+        Activity::default(),
+        discover,
+        max_results,
+    ));
+    let sources = service.register()?;
     info!("All providers registered, acquiring {}", BUSNAME);
-    context
-        .block_on(request_name_exclusive(
-            connection.inner(),
-            WellKnownName::try_from(BUSNAME).unwrap(),
-        ))
-        .with_context(|| format!("Failed to request {}", BUSNAME))?;
+    request_name(&connection, replace).with_context(|| format!("Failed to request {}", BUSNAME))?;
 
     info!("Acquired name {}, starting server and main loop", BUSNAME);
 
-    context.spawn_local(run_server(connection.inner().clone(), object_server));
+    context.spawn_local(serve(service.clone()));
+    warm_up(sources);
+    register_again_when_providers_change(service.clone());
+    register_again_on_sighup(service.clone());
+    quit_when_idle(
+        mainloop.clone(),
+        service.activity.clone(),
+        service.idle_timeout.clone(),
+    );
+    if let Some(unique_name) = connection.unique_name() {
+        quit_when_replaced(mainloop.clone(), unique_name.to_string());
+    }
 
     mainloop.run();
     Ok(())
@@ -278,14 +2258,51 @@ Set $RUST_LOG to control the log level",
                 .long("--providers")
                 .help("List all providers"),
         )
+        .arg(
+            Arg::with_name("discover")
+                .long("--discover")
+                .help("Discover and add providers for VSCode variants among installed apps"),
+        )
+        .arg(
+            Arg::with_name("replace")
+                .long("--replace")
+                .help("Replace a running instance of this service"),
+        )
+        .arg(
+            Arg::with_name("max_results")
+                .long("--max-results")
+                .value_name("N")
+                .validator(|value| {
+                    value
+                        .parse::<usize>()
+                        .map(|_| ())
+                        .map_err(|error| error.to_string())
+                })
+                .help("Return at most N results for each search"),
+        )
         .arg(
             Arg::with_name("journal_log")
                 .long("--journal-log")
                 .help("Directly log to the systemd journal instead of stdout"),
         );
     let matches = app.get_matches();
+    add_snapd_data_dir();
+    let discover = matches.is_present("discover");
+    let max_results = value_t!(matches, "max_results", usize).ok();
+    let replace = matches.is_present("replace");
     if matches.is_present("providers") {
-        let mut labels: Vec<&'static str> = PROVIDERS.iter().map(|p| p.label).collect();
+        let user_providers = match dirs::config_dir() {
+            Some(user_config_dir) => load_additional_providers(&user_config_dir, discover),
+            None => ProvidersConfig::default(),
+        };
+        let apps = AppRegistry::new();
+        let mut labels: Vec<String> = all_providers()
+            .map(|p| match p.find_app(&apps) {
+                Some((_, app)) => p.label_for(&app),
+                None => p.label.to_string(),
+            })
+            .chain(user_providers.providers.iter().map(|p| p.label.clone()))
+            .collect();
         labels.sort_unstable();
         for label in labels {
             println!("{}", label)
@@ -299,7 +2316,11 @@ Set $RUST_LOG to control the log level",
             env!("CARGO_PKG_VERSION")
         );
 
-        if let Err(err) = start_dbus_service() {
+        if let Err(err) = start_dbus_service(discover, max_results, replace) {
+            if let Some(name_taken) = err.downcast_ref::<NameTaken>() {
+                error!("{}", name_taken);
+                std::process::exit(EXIT_NAME_TAKEN);
+            }
             error!("Failed to start DBus event loop: {}", err);
             std::process::exit(1);
         }
@@ -308,67 +2329,434 @@ Set $RUST_LOG to control the log level",
 
 #[cfg(test)]
 mod tests {
-    use crate::Storage;
+    use crate::dedup::SharedWorkspaces;
+    use crate::mounts::NetworkMounts;
+    use crate::provider::{WorkspaceTarget, Workspaces};
+    use crate::storage::RecentWorkspace;
+    use crate::{
+        code_server_item, code_server_url, data_dirs_with_snapd, describe_path, is_superseded,
+        parse_code_server_bind_addr, path_segments, path_suffix, portable_config_dirs, recent_item,
+        resolve_local_items, search_object_path, user_data_dir_from_args, workspace_uris, Argv,
+        ConfigLocation, Product, BUSNAME,
+    };
+    use std::collections::VecDeque;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    const CODE: &str = "/de/swsnr/searchprovider/vscode/code";
+    const CODIUM: &str = "/de/swsnr/searchprovider/vscode/codium";
+
+    fn search_provider_call(path: &str, member: &str) -> zbus::Message {
+        zbus::Message::method(
+            Some(":1.42"),
+            Some(BUSNAME),
+            path,
+            Some("org.gnome.Shell.SearchProvider2"),
+            member,
+            &(vec!["mdcat"],),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn add_snapd_to_data_dirs() {
+        assert_eq!(
+            data_dirs_with_snapd(Some("/usr/share")).as_deref(),
+            Some("/usr/share:/var/lib/snapd/desktop")
+        );
+        assert_eq!(
+            data_dirs_with_snapd(None).as_deref(),
+            Some("/usr/local/share:/usr/share:/var/lib/snapd/desktop")
+        );
+        assert_eq!(
+            data_dirs_with_snapd(Some("/usr/share:/var/lib/snapd/desktop")),
+            None
+        );
+    }
 
     #[test]
-    fn read_recent_workspaces_code_1_54() {
-        let data: &[u8] = include_bytes!("tests/code_1_54_storage.json");
-        let storage = Storage::read(data).unwrap();
-        assert!(
-            &storage.opened_paths_list.is_some(),
-            "opened paths list missing"
-        );
-        assert!(
-            &storage
-                .opened_paths_list
-                .as_ref()
-                .unwrap()
-                .workspaces3
-                .is_some(),
-            "workspaces3 missing"
+    fn resolve_config_locations() {
+        let config_dir = Path::new("/home/foo/.config");
+        let home_dir = Path::new("/home/foo");
+        assert_eq!(
+            ConfigLocation::User {
+                dirnames: &["Code - OSS", "Code"]
+            }
+            .resolve(config_dir, home_dir),
+            vec![
+                Path::new("/home/foo/.config/Code - OSS"),
+                Path::new("/home/foo/.config/Code")
+            ]
+        );
+        assert_eq!(
+            ConfigLocation::Flatpak {
+                app_id: "com.visualstudio.code",
+                dirnames: &["Code"]
+            }
+            .resolve(config_dir, home_dir),
+            vec![Path::new(
+                "/home/foo/.var/app/com.visualstudio.code/config/Code"
+            )]
         );
         assert_eq!(
-            storage.into_workspace_urls(),
+            ConfigLocation::Snap {
+                name: "code",
+                dirnames: &["Code"]
+            }
+            .resolve(config_dir, home_dir),
             vec![
-                "file:///home/foo//mdcat",
-                "file:///home/foo//gnome-jetbrains-search-provider",
-                "file:///home/foo//gnome-shell",
-                "file:///home/foo//sbctl",
+                Path::new("/home/foo/snap/code/current/.config/Code"),
+                Path::new("/home/foo/.config/Code")
             ]
-        )
+        );
+        assert_eq!(
+            ConfigLocation::Home {
+                dirnames: &[".theia"]
+            }
+            .resolve(config_dir, home_dir),
+            vec![Path::new("/home/foo/.theia")]
+        );
+    }
+
+    #[test]
+    fn product_labels() {
+        let data: &[u8] =
+            br#"{"nameShort": "Code", "nameLong": "Visual Studio Code", "quality": "stable"}"#;
+        assert_eq!(Product::read(data).unwrap().label(), "Visual Studio Code");
+        let data: &[u8] = br#"{"nameLong": "Visual Studio Code - Insiders", "quality": "insider"}"#;
+        assert_eq!(
+            Product::read(data).unwrap().label(),
+            "Visual Studio Code - Insiders"
+        );
+        let data: &[u8] = br#"{"nameLong": "Code - OSS", "quality": "exploration"}"#;
+        assert_eq!(
+            Product::read(data).unwrap().label(),
+            "Code - OSS (exploration)"
+        );
+        let data: &[u8] = br#"{"nameLong": "VSCodium"}"#;
+        assert_eq!(Product::read(data).unwrap().label(), "VSCodium");
     }
 
     #[test]
-    fn read_recent_workspaces_code_1_55() {
-        let data: &[u8] = include_bytes!("tests/code_1_55_storage.json");
-        let storage = Storage::read(data).unwrap();
-        assert!(
-            &storage.opened_paths_list.is_some(),
-            "opened paths list missing"
-        );
-        assert!(
-            &storage
-                .opened_paths_list
-                .as_ref()
-                .unwrap()
-                .entries
-                .is_some(),
-            "entries missing"
+    fn user_data_dir_in_args() {
+        assert_eq!(
+            user_data_dir_from_args(&["code", "--user-data-dir", "/tmp/foo", "%F"]),
+            Some(PathBuf::from("/tmp/foo"))
+        );
+        assert_eq!(
+            user_data_dir_from_args(&["code", "--user-data-dir=/tmp/foo", "%F"]),
+            Some(PathBuf::from("/tmp/foo"))
+        );
+        assert_eq!(
+            user_data_dir_from_args(&["code", "--unity-launch", "%F"]),
+            None
         );
+    }
 
+    #[test]
+    fn read_argv_json_with_comments() {
+        let argv = Argv::read(
+            r#"// This configuration file allows you to pass permanent command line arguments to VS Code.
+{
+	// Use software rendering instead of hardware accelerated rendering.
+	// "disable-hardware-acceleration": true,
+
+	"enable-crash-reporter": false,
+	"user-data-dir": "/home/foo/vscode"
+}"#,
+        )
+        .unwrap();
+        assert_eq!(argv.user_data_dir, Some(PathBuf::from("/home/foo/vscode")));
+    }
+
+    #[test]
+    fn portable_config_dirs_next_to_executable() {
+        assert_eq!(
+            portable_config_dirs(Path::new("/opt/VSCode-linux-x64/code"), None),
+            vec![
+                Path::new("/opt/VSCode-linux-x64/data/user-data"),
+                Path::new("/opt/data/user-data")
+            ]
+        );
         assert_eq!(
-            storage.into_workspace_urls(),
+            portable_config_dirs(
+                Path::new("/opt/VSCode-linux-x64/bin/code"),
+                Some(Path::new("/home/foo/vscode-data"))
+            ),
             vec![
-                "file:///home/foo//mdcat",
-                "file:///home/foo//gnome-jetbrains-search-provider",
-                "file:///home/foo//gnome-shell",
-                "file:///home/foo//sbctl",
+                Path::new("/home/foo/vscode-data/user-data"),
+                Path::new("/opt/VSCode-linux-x64/bin/data/user-data"),
+                Path::new("/opt/VSCode-linux-x64/data/user-data")
             ]
         );
     }
 
+    #[test]
+    fn code_server_bind_addr_from_config() {
+        let config = "bind-addr: 127.0.0.1:8443\nauth: password\npassword: foo\ncert: false\n";
+        assert_eq!(parse_code_server_bind_addr(config), Some("127.0.0.1:8443"));
+        assert_eq!(parse_code_server_bind_addr("auth: none\n"), None);
+    }
+
+    #[test]
+    fn code_server_url_for_workspace() {
+        assert_eq!(
+            code_server_url("127.0.0.1:8080", "file:///home/foo/mdcat"),
+            Some("http://127.0.0.1:8080/?folder=/home/foo/mdcat".to_string())
+        );
+        assert_eq!(
+            code_server_url("127.0.0.1:8080", "file:///home/foo/foo.code-workspace"),
+            Some("http://127.0.0.1:8080/?workspace=/home/foo/foo.code-workspace".to_string())
+        );
+        assert_eq!(
+            code_server_url("127.0.0.1:8080", "vscode-remote://ssh-remote+foo/home/foo"),
+            None
+        );
+    }
+
+    #[test]
+    fn path_segments_below_home() {
+        let home_dir = Path::new("/home/foo");
+        assert_eq!(
+            path_segments("/home/foo/work/acme/billing", Some(home_dir)),
+            vec!["work", "acme", "billing"]
+        );
+        assert_eq!(
+            path_segments("/srv/acme/billing", Some(home_dir)),
+            vec!["srv", "acme", "billing"]
+        );
+        assert_eq!(
+            path_segments("/home/foo/mdcat", None),
+            vec!["home", "foo", "mdcat"]
+        );
+    }
+
+    #[test]
+    fn describe_paths() {
+        let home_dir = Path::new("/home/foo");
+        assert_eq!(
+            describe_path("/home/foo/work/acme/billing", Some(home_dir)).as_deref(),
+            Some("~/work/acme")
+        );
+        assert_eq!(
+            describe_path("/home/foo/mdcat/", Some(home_dir)).as_deref(),
+            Some("~")
+        );
+        assert_eq!(
+            describe_path("/srv/acme/billing", Some(home_dir)).as_deref(),
+            Some("/srv/acme")
+        );
+        assert_eq!(describe_path("/", Some(home_dir)), None);
+    }
+
+    #[test]
+    fn path_suffixes() {
+        assert_eq!(
+            path_suffix("/home/foo/src/acme/billing").as_deref(),
+            Some("acme/billing")
+        );
+        assert_eq!(
+            path_suffix("/acme/billing/").as_deref(),
+            Some("acme/billing")
+        );
+        assert_eq!(path_suffix("/billing"), None);
+    }
+
+    #[test]
+    fn recent_item_names() {
+        let item = recent_item(
+            RecentWorkspace::new("file:///home/foo/mdcat".to_string()),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(item.name, "mdcat");
+        assert_eq!(item.path.as_deref(), Some("/home/foo/mdcat"));
+        let item = recent_item(
+            RecentWorkspace::new("file:///home/foo/My%20Project".to_string()),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(item.name, "My Project");
+        assert_eq!(item.path.as_deref(), Some("/home/foo/My Project"));
+        assert_eq!(
+            item.target,
+            WorkspaceTarget::Uri("file:///home/foo/My%20Project".into())
+        );
+        let item = recent_item(
+            RecentWorkspace::new("file:///home/foo/projects/foo.code-workspace".to_string()),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(item.name, "foo");
+        let item = recent_item(
+            RecentWorkspace {
+                label: Some("My mdcat".to_string()),
+                ..RecentWorkspace::new("file:///home/foo/mdcat".to_string())
+            },
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(item.name, "My mdcat");
+        let item = recent_item(
+            RecentWorkspace {
+                profile: Some("Work".to_string()),
+                ..RecentWorkspace::new("file:///home/foo/mdcat".to_string())
+            },
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(item.name, "mdcat (Work)");
+        let item = recent_item(
+            RecentWorkspace::new("vscode-remote://ssh-remote%2Bfoo/home/foo/mdcat".to_string()),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(item.name, "mdcat — ssh: foo");
+        assert_eq!(
+            item.target,
+            WorkspaceTarget::RemoteFolder("vscode-remote://ssh-remote%2Bfoo/home/foo/mdcat".into())
+        );
+        let item = recent_item(
+            RecentWorkspace::new(
+                "vscode-remote://ssh-remote%2Bfoo/home/foo/foo.code-workspace".to_string(),
+            ),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            item.target,
+            WorkspaceTarget::FileUri(
+                "vscode-remote://ssh-remote%2Bfoo/home/foo/foo.code-workspace".into()
+            )
+        );
+        let item = recent_item(
+            RecentWorkspace::file("file:///home/foo/mdcat/README.md".to_string()),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            item.target,
+            WorkspaceTarget::FileUri("file:///home/foo/mdcat/README.md".into())
+        );
+    }
+
+    #[test]
+    fn deduplicate_code_server_workspaces() {
+        let url = "file:///home/foo/mdcat";
+        let local_item = recent_item(RecentWorkspace::new(url.to_string()), 0, None).unwrap();
+        let served_item = code_server_item(
+            "127.0.0.1:8080",
+            RecentWorkspace::new(url.to_string()),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            served_item.target,
+            WorkspaceTarget::Uri("http://127.0.0.1:8080/?folder=/home/foo/mdcat".into())
+        );
+        let shared = SharedWorkspaces::default();
+        let vscode = shared.deduplicator(0);
+        let code_server = shared.deduplicator(1);
+        let vscode_items: Workspaces =
+            std::iter::once(("vscode".into(), local_item.clone())).collect();
+        let code_server_items: Workspaces =
+            std::iter::once(("code-server".into(), served_item.clone())).collect();
+        vscode.publish(workspace_uris(&vscode_items));
+        code_server.publish(workspace_uris(&code_server_items));
+        assert!(code_server.is_duplicate(&served_item.url));
+        assert!(!vscode.is_duplicate(&local_item.url));
+        assert!(code_server_item(
+            "127.0.0.1:8080",
+            RecentWorkspace::new("vscode-remote://ssh-remote%2Bfoo/home/foo/mdcat".to_string()),
+            0,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn mark_items_on_network_mounts() {
+        let network_mounts =
+            NetworkMounts::parse("server:/export/home /mnt/home nfs4 rw,relatime 0 0\n");
+        let mut items: Workspaces = [
+            "file:///mnt/home/foo/mdcat",
+            "file:///home/foo/mdcat",
+            "vscode-remote://ssh-remote%2Bfoo/mnt/home/foo/mdcat",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(recency, url)| {
+            let workspace = RecentWorkspace::new(url.to_string());
+            (
+                (*url).into(),
+                recent_item(workspace, recency, None).unwrap(),
+            )
+        })
+        .collect();
+        resolve_local_items(&mut items, &network_mounts, false);
+        assert!(items["file:///mnt/home/foo/mdcat"].on_network_mount);
+        assert_eq!(items["file:///mnt/home/foo/mdcat"].canonical_path, None);
+        assert!(!items["file:///home/foo/mdcat"].on_network_mount);
+        assert!(!items["vscode-remote://ssh-remote%2Bfoo/mnt/home/foo/mdcat"].on_network_mount);
+    }
+
+    #[test]
+    fn search_object_path_of_searches() {
+        let message = search_provider_call(CODE, "GetInitialResultSet");
+        assert_eq!(search_object_path(&message).as_deref(), Some(CODE));
+        let message = search_provider_call(CODIUM, "GetSubsearchResultSet");
+        assert_eq!(search_object_path(&message).as_deref(), Some(CODIUM));
+        let message = search_provider_call(CODE, "GetResultMetas");
+        assert_eq!(search_object_path(&message), None);
+        let message = search_provider_call(CODE, "ActivateResult");
+        assert_eq!(search_object_path(&message), None);
+    }
+
+    #[test]
+    fn skip_superseded_searches() {
+        let search = search_provider_call(CODE, "GetInitialResultSet");
+        let waiting = |calls: &[(&str, &str)]| -> VecDeque<zbus::Result<Arc<zbus::Message>>> {
+            calls
+                .iter()
+                .map(|(path, member)| Ok(Arc::new(search_provider_call(path, member))))
+                .collect()
+        };
+        assert!(!is_superseded(&search, &waiting(&[])));
+        assert!(is_superseded(
+            &search,
+            &waiting(&[(CODE, "GetSubsearchResultSet")])
+        ));
+        assert!(is_superseded(
+            &search,
+            &waiting(&[
+                (CODIUM, "GetInitialResultSet"),
+                (CODE, "GetInitialResultSet")
+            ])
+        ));
+        // Searches of other providers and other calls don't supersede a search
+        assert!(!is_superseded(
+            &search,
+            &waiting(&[(CODIUM, "GetInitialResultSet"), (CODE, "GetResultMetas")])
+        ));
+        // Only searches get superseded
+        let metas = search_provider_call(CODE, "GetResultMetas");
+        assert!(!is_superseded(
+            &metas,
+            &waiting(&[(CODE, "GetInitialResultSet")])
+        ));
+    }
+
     mod providers {
-        use crate::{BUSNAME, PROVIDERS};
+        use crate::{all_providers, BUSNAME};
         use anyhow::{Context, Result};
         use ini::Ini;
         use std::collections::HashSet;
@@ -421,45 +2809,73 @@ mod tests {
         #[test]
         fn all_providers_have_a_correct_ini_file() {
             let provider_files = load_all_provider_files().unwrap();
-            for provider in PROVIDERS {
-                let provider_file = provider_files
-                    .iter()
-                    .find(|p| p.desktop_id == provider.desktop_id);
-                assert!(
-                    provider_file.is_some(),
-                    "Provider INI missing for provider {} with desktop ID {}",
-                    provider.label,
-                    provider.desktop_id
-                );
+            for provider in all_providers() {
+                for desktop_id in provider.desktop_ids {
+                    let provider_file = provider_files.iter().find(|p| p.desktop_id == *desktop_id);
+                    assert!(
+                        provider_file.is_some(),
+                        "Provider INI missing for provider {} with desktop ID {}",
+                        provider.label,
+                        desktop_id
+                    );
 
-                assert_eq!(provider_file.unwrap().object_path, provider.objpath());
-                assert_eq!(provider_file.unwrap().bus_name, BUSNAME);
-                assert_eq!(provider_file.unwrap().version, "2");
+                    assert_eq!(
+                        provider_file.unwrap().object_path,
+                        provider.objpath(desktop_id)
+                    );
+                    assert_eq!(provider_file.unwrap().bus_name, BUSNAME);
+                    assert_eq!(provider_file.unwrap().version, "2");
+                }
             }
         }
 
         #[test]
         fn no_extra_ini_files_without_providers() {
             let provider_files = load_all_provider_files().unwrap();
-            assert_eq!(PROVIDERS.len(), provider_files.len());
+            let desktop_ids = all_providers().flat_map(|p| p.desktop_ids.iter()).count();
+            assert_eq!(desktop_ids, provider_files.len());
         }
 
         #[test]
         fn desktop_ids_are_unique() {
             let mut ids = HashSet::new();
-            for provider in PROVIDERS {
-                ids.insert(provider.desktop_id);
+            let mut count = 0;
+            for provider in all_providers() {
+                for desktop_id in provider.desktop_ids {
+                    ids.insert(desktop_id);
+                    count += 1;
+                }
             }
-            assert_eq!(PROVIDERS.len(), ids.len());
+            assert_eq!(count, ids.len());
         }
 
         #[test]
         fn dbus_paths_are_unique() {
             let mut paths = HashSet::new();
-            for provider in PROVIDERS {
-                paths.insert(provider.objpath());
+            let mut count = 0;
+            for provider in all_providers() {
+                for desktop_id in provider.desktop_ids {
+                    paths.insert(provider.objpath(desktop_id));
+                    count += 1;
+                }
             }
-            assert_eq!(PROVIDERS.len(), paths.len());
+            assert_eq!(count, paths.len());
+        }
+
+        #[test]
+        fn objpath_for_alias() {
+            let provider = crate::PROVIDERS
+                .iter()
+                .find(|p| p.desktop_ids.contains(&"code.desktop"))
+                .unwrap();
+            assert_eq!(
+                provider.objpath("visual-studio-code.desktop"),
+                "/de/swsnr/searchprovider/vscode/aur/visualstudiocode"
+            );
+            assert_eq!(
+                provider.objpath("code.desktop"),
+                "/de/swsnr/searchprovider/vscode/aur/visualstudiocode/code"
+            );
         }
     }
 }
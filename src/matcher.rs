@@ -0,0 +1,168 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Algorithms to match search terms against workspaces.
+
+use log::debug;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+use crate::search::{match_term, score_terms, FoldedText, MATCH_KIND_WEIGHT};
+
+/// Match search terms against the texts of a workspace, e.g. its name and path.
+pub trait Matcher {
+    /// Score how well the search terms match any of the `texts` of a workspace.
+    ///
+    /// Return `None` if the workspace doesn't match; otherwise return a score, where greater
+    /// scores denote better matches.
+    fn score(&self, texts: &[&FoldedText]) -> Option<i64>;
+}
+
+/// Match terms fuzzily, see [`score_terms`].
+#[derive(Debug)]
+pub struct FuzzyMatcher {
+    /// The search terms.
+    terms: Vec<FoldedText>,
+}
+
+impl Matcher for FuzzyMatcher {
+    fn score(&self, texts: &[&FoldedText]) -> Option<i64> {
+        score_terms(texts, &self.terms)
+    }
+}
+
+/// Match terms as substrings, ignoring case and diacritics.
+///
+/// Rank matches like [`FuzzyMatcher`].
+#[derive(Debug)]
+pub struct SubstringMatcher {
+    /// The search terms.
+    terms: Vec<FoldedText>,
+}
+
+impl Matcher for SubstringMatcher {
+    fn score(&self, texts: &[&FoldedText]) -> Option<i64> {
+        self.terms.iter().try_fold(0, |total, term| {
+            texts
+                .iter()
+                .filter(|text| text.contains(term))
+                .filter_map(|text| match_term(term, text))
+                .map(|(kind, score)| kind as i64 * MATCH_KIND_WEIGHT + score)
+                .max()
+                .map(|score| total + score)
+        })
+    }
+}
+
+/// Match terms as case-insensitive regular expressions.
+///
+/// All matches score the same.
+#[derive(Debug)]
+pub struct RegexMatcher {
+    /// Regular expressions of all search terms.
+    ///
+    /// `None` if any term is not a valid regular expression; then nothing matches.
+    regexes: Option<Vec<Regex>>,
+}
+
+impl RegexMatcher {
+    /// Compile `terms` to regular expressions.
+    fn new(terms: &[String]) -> Self {
+        let regexes = terms
+            .iter()
+            .map(|term| RegexBuilder::new(term).case_insensitive(true).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| debug!("Invalid regular expression in {:?}: {}", terms, error))
+            .ok();
+        Self { regexes }
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn score(&self, texts: &[&FoldedText]) -> Option<i64> {
+        let regexes = self.regexes.as_ref()?;
+        if regexes
+            .iter()
+            .all(|regex| texts.iter().any(|text| regex.is_match(text.as_str())))
+        {
+            Some(0)
+        } else {
+            None
+        }
+    }
+}
+
+/// The algorithm to match search terms with.
+#[derive(Debug, Copy, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatcherKind {
+    /// Match terms fuzzily, see [`FuzzyMatcher`].
+    Fuzzy,
+    /// Match terms as substrings, see [`SubstringMatcher`].
+    Substring,
+    /// Match terms as regular expressions, see [`RegexMatcher`].
+    Regex,
+}
+
+impl Default for MatcherKind {
+    fn default() -> Self {
+        MatcherKind::Fuzzy
+    }
+}
+
+impl MatcherKind {
+    /// Create a matcher of this kind for the given search `terms`.
+    pub fn matcher(self, terms: &[String]) -> Box<dyn Matcher> {
+        let folded =
+            || -> Vec<FoldedText> { terms.iter().map(|term| FoldedText::new(term)).collect() };
+        match self {
+            MatcherKind::Fuzzy => Box::new(FuzzyMatcher { terms: folded() }),
+            MatcherKind::Substring => Box::new(SubstringMatcher { terms: folded() }),
+            MatcherKind::Regex => Box::new(RegexMatcher::new(terms)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    fn score(matcher: &dyn Matcher, texts: &[&str]) -> Option<i64> {
+        let texts: Vec<FoldedText> = texts.iter().map(|text| FoldedText::new(text)).collect();
+        matcher.score(&texts.iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn fuzzy_matcher() {
+        let matcher = MatcherKind::Fuzzy.matcher(&terms(&["gnsp"]));
+        assert!(score(matcher.as_ref(), &["gnome-search-provider"]).is_some());
+        assert!(score(matcher.as_ref(), &["mdcat"]).is_none());
+    }
+
+    #[test]
+    fn substring_matcher() {
+        let matcher = MatcherKind::Substring.matcher(&terms(&["search"]));
+        assert!(score(matcher.as_ref(), &["gnome-search-provider"]).is_some());
+        assert!(score(matcher.as_ref(), &["gnome", "Search"]).is_some());
+        let matcher = MatcherKind::Substring.matcher(&terms(&["gnsp"]));
+        assert!(score(matcher.as_ref(), &["gnome-search-provider"]).is_none());
+        let matcher = MatcherKind::Substring.matcher(&terms(&["resume"]));
+        assert!(score(matcher.as_ref(), &["Résumé-Site"]).is_some());
+    }
+
+    #[test]
+    fn regex_matcher() {
+        let matcher = MatcherKind::Regex.matcher(&terms(&["^gnome-.*-provider$"]));
+        assert_eq!(score(matcher.as_ref(), &["Gnome-Search-Provider"]), Some(0));
+        assert_eq!(score(matcher.as_ref(), &["my-gnome-search-provider"]), None);
+        let matcher = MatcherKind::Regex.matcher(&terms(&["gnome", "("]));
+        assert_eq!(score(matcher.as_ref(), &["gnome-search-provider"]), None);
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Network file systems.
+//!
+//! Accessing paths on unreachable network file systems can block for a long time, so we avoid
+//! looking at workspaces on network file systems.
+
+use std::path::{Path, PathBuf};
+
+use log::{debug, trace};
+
+/// File system types of network file systems.
+const NETWORK_FILESYSTEMS: [&str; 13] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.gvfsd-fuse",
+    "davfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+];
+
+/// Unescape octal escapes in a mount point from `/proc/self/mounts`, e.g. `\040` for a space.
+fn unescape_mount_point(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        let escape = rest.get(index + 1..index + 4);
+        match escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                unescaped.push(byte as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// The mount points of all network file systems.
+#[derive(Debug, Default, Clone)]
+pub struct NetworkMounts {
+    /// Mount points of network file systems.
+    mount_points: Vec<PathBuf>,
+}
+
+impl NetworkMounts {
+    /// Parse network mounts from the `contents` of `/proc/self/mounts`.
+    pub fn parse(contents: &str) -> Self {
+        let mount_points = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let mount_point = fields.nth(1)?;
+                let fstype = fields.next()?;
+                if NETWORK_FILESYSTEMS.contains(&fstype) {
+                    trace!("Found network mount {} ({})", mount_point, fstype);
+                    Some(PathBuf::from(unescape_mount_point(mount_point)))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Self { mount_points }
+    }
+
+    /// Read the current network mounts of this process.
+    ///
+    /// Return no network mounts if mounts can't be read.
+    pub fn current() -> Self {
+        match std::fs::read_to_string("/proc/self/mounts") {
+            Ok(contents) => Self::parse(&contents),
+            Err(error) => {
+                debug!("Failed to read mounts: {}", error);
+                Self::default()
+            }
+        }
+    }
+
+    /// Whether `path` is on a network file system.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.mount_points
+            .iter()
+            .any(|mount_point| path.starts_with(mount_point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_network_mounts() {
+        let mounts = NetworkMounts::parse(
+            "/dev/nvme0n1p2 / btrfs rw,relatime 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+server:/export/home /mnt/home nfs4 rw,relatime 0 0
+foo@build: /home/foo/remote\\040build fuse.sshfs rw 0 0
+",
+        );
+        assert!(mounts.contains(Path::new("/mnt/home/foo/mdcat")));
+        assert!(mounts.contains(Path::new("/home/foo/remote build/mdcat")));
+        assert!(!mounts.contains(Path::new("/home/foo/mdcat")));
+        assert!(!mounts.contains(Path::new("/mnt/homework")));
+    }
+}
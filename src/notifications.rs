@@ -0,0 +1,68 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Desktop notifications.
+//!
+//! Users don't see the journal of this service, so we tell them about failures which they would
+//! otherwise perceive as "nothing happened", e.g. a workspace which fails to open.
+
+use std::collections::HashMap;
+
+use log::{debug, warn};
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::glib;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+/// The icon of failure notifications.
+const ERROR_ICON: &str = "dialog-error";
+
+/// Show a notification about a failure on behalf of the app named `app_name`.
+///
+/// Send the notification asynchronously, and only log if the notification fails.  Gnome Shell
+/// renders markup in the `body`, so escape it; error messages often include paths and URLs.
+pub fn notify_failure(app_name: &str, summary: &str, body: &str) {
+    let connection = match gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>) {
+        Ok(connection) => connection,
+        Err(error) => {
+            warn!(
+                "Failed to connect to session bus for notification: {}",
+                error
+            );
+            return;
+        }
+    };
+    let hints: HashMap<String, glib::Variant> = HashMap::new();
+    let body = glib::markup_escape_text(body);
+    let parameters = (
+        app_name,
+        0u32,
+        ERROR_ICON,
+        summary,
+        body.as_str(),
+        Vec::<String>::new(),
+        hints,
+        -1i32,
+    )
+        .to_variant();
+    debug!("Notifying about failure: {}", summary);
+    connection.call(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+        "Notify",
+        Some(&parameters),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        None::<&gio::Cancellable>,
+        |result| {
+            if let Err(error) = result {
+                warn!("Failed to show notification: {}", error);
+            }
+        },
+    );
+}
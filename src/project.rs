@@ -0,0 +1,211 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Information about the project in a local workspace.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use log::trace;
+
+/// Names of README files, in order of preference.
+const README_NAMES: [&str; 7] = [
+    "README.md",
+    "README.markdown",
+    "README.rst",
+    "README.adoc",
+    "README.txt",
+    "README",
+    "readme.md",
+];
+
+/// How many lines of a README to look at for a title.
+const README_TITLE_LINES: usize = 50;
+
+/// Files which mark the language of a project, in order of precedence.
+const LANGUAGE_MARKERS: [(&str, &str); 22] = [
+    ("Cargo.toml", "Rust"),
+    ("go.mod", "Go"),
+    ("tsconfig.json", "TypeScript"),
+    ("deno.json", "TypeScript"),
+    ("package.json", "JavaScript"),
+    ("pyproject.toml", "Python"),
+    ("setup.py", "Python"),
+    ("requirements.txt", "Python"),
+    ("build.gradle.kts", "Kotlin"),
+    ("pom.xml", "Java"),
+    ("build.gradle", "Java"),
+    ("build.sbt", "Scala"),
+    ("Gemfile", "Ruby"),
+    ("composer.json", "PHP"),
+    ("mix.exs", "Elixir"),
+    ("stack.yaml", "Haskell"),
+    ("cabal.project", "Haskell"),
+    ("dune-project", "OCaml"),
+    ("pubspec.yaml", "Dart"),
+    ("Package.swift", "Swift"),
+    ("CMakeLists.txt", "C/C++"),
+    ("flake.nix", "Nix"),
+];
+
+/// Detect the language of the project in `workspace` by marker files.
+///
+/// Only look for marker files directly in `workspace`, without scanning its contents.
+fn detect_language(workspace: &Path) -> Option<&'static str> {
+    LANGUAGE_MARKERS
+        .iter()
+        .find(|(marker, _)| workspace.join(marker).is_file())
+        .map(|(_, language)| *language)
+}
+
+/// Whether `line` underlines a heading, like `===` in Markdown or reStructuredText.
+fn is_underline(line: &str) -> bool {
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(first) if "=-~#*^".contains(first) => 3 <= line.len() && chars.all(|c| c == first),
+        _ => false,
+    }
+}
+
+/// Find the title in the `contents` of a README file.
+///
+/// Return the first heading in Markdown (`# Title` or underlined), reStructuredText, or
+/// AsciiDoc (`= Title`).
+fn readme_title(contents: &str) -> Option<String> {
+    let lines: Vec<&str> = contents
+        .lines()
+        .take(README_TITLE_LINES)
+        .map(str::trim)
+        .collect();
+    for (index, line) in lines.iter().enumerate() {
+        let title = if let Some(heading) = line.strip_prefix('#') {
+            Some(heading.trim_start_matches('#').trim_end_matches('#'))
+        } else if let Some(heading) = line.strip_prefix("= ") {
+            Some(heading)
+        } else if !line.is_empty()
+            && !is_underline(line)
+            && lines
+                .get(index + 1)
+                .map_or(false, |next| is_underline(next))
+        {
+            Some(*line)
+        } else {
+            None
+        };
+        if let Some(title) = title.map(str::trim).filter(|title| !title.is_empty()) {
+            return Some(title.to_string());
+        }
+    }
+    None
+}
+
+/// Find the README file in `workspace`, along with its modification time.
+fn find_readme(workspace: &Path) -> Option<(PathBuf, SystemTime)> {
+    README_NAMES.iter().find_map(|name| {
+        let path = workspace.join(name);
+        let mtime = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        Some((path, mtime))
+    })
+}
+
+/// A cache of information about projects.
+///
+/// Remember information along with the modification time of the file it came from, to only read
+/// it again after it changed.
+#[derive(Debug, Default)]
+pub struct ProjectCache {
+    /// The README file, its modification time and its title, by workspace.
+    readme_titles: HashMap<PathBuf, (PathBuf, SystemTime, Option<String>)>,
+    /// The modification time of the workspace directory and its language, by workspace.
+    languages: HashMap<PathBuf, (SystemTime, Option<&'static str>)>,
+}
+
+impl ProjectCache {
+    /// The title of the README of the project in `workspace`, if any.
+    pub fn readme_title(&mut self, workspace: &Path) -> Option<String> {
+        let (readme, mtime) = find_readme(workspace)?;
+        if let Some((cached_readme, cached_mtime, title)) = self.readme_titles.get(workspace) {
+            if *cached_readme == readme && *cached_mtime == mtime {
+                return title.clone();
+            }
+        }
+        trace!("Reading title from {}", readme.display());
+        let title = std::fs::read_to_string(&readme)
+            .ok()
+            .and_then(|contents| readme_title(&contents));
+        self.readme_titles
+            .insert(workspace.to_path_buf(), (readme, mtime, title.clone()));
+        title
+    }
+
+    /// The language of the project in `workspace`, if known.
+    ///
+    /// Detect the language again only if files in `workspace` were added or removed since.
+    pub fn language(&mut self, workspace: &Path) -> Option<&'static str> {
+        let mtime = std::fs::metadata(workspace)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        match self.languages.get(workspace) {
+            Some((cached_mtime, language)) if *cached_mtime == mtime => *language,
+            _ => {
+                trace!("Detecting language of {}", workspace.display());
+                let language = detect_language(workspace);
+                self.languages
+                    .insert(workspace.to_path_buf(), (mtime, language));
+                language
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_titles() {
+        assert_eq!(
+            readme_title("[![CI](badge.svg)](ci)\n\n# mdcat\n\nCat for markdown").as_deref(),
+            Some("mdcat")
+        );
+        assert_eq!(
+            readme_title("## Billing service ##\n").as_deref(),
+            Some("Billing service")
+        );
+        assert_eq!(
+            readme_title("Billing service\n===============\n").as_deref(),
+            Some("Billing service")
+        );
+        assert_eq!(readme_title("Just some text\n\nMore text\n"), None);
+    }
+
+    #[test]
+    fn detect_language_of_this_project() {
+        assert_eq!(
+            detect_language(Path::new(env!("CARGO_MANIFEST_DIR"))),
+            Some("Rust")
+        );
+        assert_eq!(
+            detect_language(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src")),
+            None
+        );
+    }
+
+    #[test]
+    fn rst_and_asciidoc_titles() {
+        assert_eq!(
+            readme_title("=======\nsvc-api\n=======\n\nAn API").as_deref(),
+            Some("svc-api")
+        );
+        assert_eq!(
+            readme_title("= Billing\n:toc:\n").as_deref(),
+            Some("Billing")
+        );
+    }
+}
@@ -0,0 +1,1353 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The search provider DBus interface for recent workspaces.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use zbus::dbus_interface;
+use zvariant::Value;
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+use crate::activation::{next_startup_id, set_startup_id, unset_startup_id};
+use crate::cancel::{CancellationToken, SearchGeneration};
+use crate::config::{Settings, WindowMode};
+use crate::dedup::Deduplicator;
+use crate::git::{clone_url, is_clone_url, BranchCache, GitRemote};
+use crate::history::{now, LaunchHistory};
+use crate::idle::Activity;
+use crate::notifications::notify_failure;
+use crate::project::ProjectCache;
+use crate::search::{parse_filters, rank_matches, split_terms, Candidate, Filter, FoldedText};
+use crate::session::SessionEnvironment;
+use crate::storage::{CodeWorkspaceNames, RecentKind, CODE_WORKSPACE_EXTENSION};
+use crate::IndexMap;
+
+/// How to open a workspace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WorkspaceTarget {
+    /// A URI to open with the app.
+    ///
+    /// Gio hands `file://` URIs to the app as paths.
+    Uri(Arc<str>),
+    /// A remote folder to open with `--folder-uri`.
+    RemoteFolder(Arc<str>),
+    /// A file to open with `--file-uri`.
+    ///
+    /// Recent files, and workspace files on remotes.  With `--file-uri` the editor opens the
+    /// file as a file, even if it doesn't exist anymore.
+    FileUri(Arc<str>),
+    /// A git repository to clone from `url` into `directory`, and then open.
+    Clone {
+        /// The URL to clone from.
+        url: Arc<str>,
+        /// The directory to clone into.
+        directory: PathBuf,
+    },
+}
+
+impl WorkspaceTarget {
+    /// The URI to open.
+    pub fn uri(&self) -> &str {
+        match self {
+            WorkspaceTarget::Uri(uri)
+            | WorkspaceTarget::RemoteFolder(uri)
+            | WorkspaceTarget::FileUri(uri)
+            | WorkspaceTarget::Clone { url: uri, .. } => uri,
+        }
+    }
+}
+
+/// A workspace to show in search results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceItem {
+    /// The human readable name of the workspace.
+    pub name: String,
+    /// The URL of the workspace in the storage of the app, e.g. `file:///home/foo/mdcat`.
+    ///
+    /// Identifies the workspace across providers, also for providers which open it with another
+    /// URL, e.g. code-server.  Usually shared with the target.
+    pub url: Arc<str>,
+    /// How to open the workspace.
+    pub target: WorkspaceTarget,
+    /// The full path of the workspace, if any.
+    pub path: Option<String>,
+    /// Segments of the path of the workspace below the home directory, for matching.
+    pub segments: Vec<String>,
+    /// The last two segments of the path of the workspace, e.g. `acme/billing`, if any.
+    ///
+    /// Terms with a slash match against this suffix, to tell apart workspaces of the same name
+    /// by their parent directory.
+    pub suffix: Option<String>,
+    /// The position of the workspace in the list of recent workspaces; 0 is most recent.
+    pub recency: usize,
+    /// What kind of workspace this is.
+    pub kind: RecentKind,
+    /// Whether this is a remote workspace.
+    pub remote: bool,
+    /// The `origin` remote of the git repository of the workspace, if known.
+    pub git_remote: Option<GitRemote>,
+    /// A description of the location of the workspace, e.g. `~/work/acme`, if any.
+    pub description: Option<String>,
+    /// When the app last opened the workspace, if known.
+    pub last_opened: Option<SystemTime>,
+    /// The name of the user data profile of the workspace, if any.
+    pub profile: Option<String>,
+    /// The path of the local `.code-workspace` file of this workspace, if it has no label.
+    ///
+    /// Take the name of the workspace from this file if it has one.
+    pub workspace_file: Option<String>,
+    /// The full path of the workspace with all symlinks resolved, if it's local.
+    ///
+    /// Resolved when reading workspaces, to tell apart workspaces opened through symlinks
+    /// without touching the file system on every search.
+    #[serde(default)]
+    pub canonical_path: Option<PathBuf>,
+    /// Whether the workspace is on a network file system.
+    ///
+    /// Checked when reading workspaces, to avoid blocking on unreachable network mounts without
+    /// reading mounts on every search.
+    #[serde(default)]
+    pub on_network_mount: bool,
+}
+
+/// Workspaces by ID, most recent first.
+///
+/// IDs are reference-counted, to pass them through searches without copying them.
+pub type Workspaces = IndexMap<Arc<str>, WorkspaceItem>;
+
+/// A source of workspaces for a provider.
+pub trait WorkspacesSource {
+    /// Find recent workspaces.
+    ///
+    /// Sources may return the same shared workspaces for many searches, as long as workspaces
+    /// don't change.
+    fn find_workspaces(&self) -> Result<Arc<Workspaces>>;
+}
+
+/// Find the item with the given `id` among `items` and the `clone_result`, if any.
+fn find_item<'a>(
+    items: &'a Workspaces,
+    clone_result: Option<&'a (Arc<str>, WorkspaceItem)>,
+    id: &str,
+) -> Option<&'a WorkspaceItem> {
+    items.get(id).or_else(|| {
+        clone_result
+            .filter(|(clone_id, _)| &**clone_id == id)
+            .map(|(_, item)| item)
+    })
+}
+
+/// Turn `ids` into search results for Gnome Shell.
+fn result_ids(ids: Vec<Arc<str>>) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+/// The themed icon to use as emblem for remote workspaces.
+const REMOTE_EMBLEM: &str = "network-server-symbolic";
+
+/// The name of the themed icon installed at `path`, if `path` is in an icon theme.
+///
+/// Icon themes live in `icons` directories, e.g. `/usr/share/icons/hicolor/256x256/apps/code.png`
+/// is the themed icon `code`.
+fn theme_icon_name(path: &Path) -> Option<String> {
+    let in_theme = path
+        .parent()?
+        .ancestors()
+        .any(|dir| dir.file_name().map_or(false, |name| name == "icons"));
+    if in_theme {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+    } else {
+        None
+    }
+}
+
+/// Use a themed icon for `icon` if possible.
+///
+/// Desktop files sometimes refer to an icon in an icon theme by its full path; use the icon by
+/// name instead, to let Gnome Shell pick the icon from the user's icon theme in the right size.
+fn prefer_themed_icon(icon: gio::Icon) -> gio::Icon {
+    let name = icon
+        .downcast_ref::<gio::FileIcon>()
+        .and_then(|icon| icon.file().path())
+        .and_then(|path| theme_icon_name(&path));
+    match name {
+        Some(name) => gio::ThemedIcon::new(&name).upcast(),
+        None => icon,
+    }
+}
+
+/// Quote `s` for a shell command line.
+///
+/// Also escape `%`, because Gio treats command lines of apps like `Exec` lines of desktop files,
+/// where `%` starts a field code.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''").replace('%', "%%"))
+}
+
+/// Remove field codes from the `Exec` line of a desktop file.
+///
+/// Also remove the file forwarding markers of Flatpak.
+fn strip_field_codes(exec: &str) -> String {
+    exec.split_whitespace()
+        .filter(|arg| !(arg.starts_with('%') || *arg == "@@" || *arg == "@@u"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Terminals of Gnome, in order of preference.
+///
+/// All of these accept the directory to open after `--working-directory`.
+const TERMINALS: [&str; 3] = ["ptyxis", "kgx", "gnome-terminal"];
+
+/// The prefix of IDs of results which clone a repository.
+const CLONE_ID_PREFIX: &str = "vscode-search-provider-clone-";
+
+/// URI schemes of workspaces which we open.
+///
+/// `http` and `https` for code-server, and the schemes of local, remote and virtual workspaces.
+const SUPPORTED_SCHEMES: [&str; 5] = ["file", "http", "https", "vscode-remote", "vscode-vfs"];
+
+/// How long a search may take at most.
+///
+/// Gnome Shell stops waiting for slow providers, so we rather return the matches we found until
+/// then.
+const SEARCH_BUDGET: Duration = Duration::from_millis(100);
+
+/// Check that we can safely hand `uri` to an app.
+///
+/// Reject URIs with unsupported schemes, and URIs with whitespace or control characters, which
+/// proper URIs percent-encode.  Since valid URIs start with their scheme, they can't pass as
+/// command line flags either.
+fn validate_uri(uri: &str) -> Result<()> {
+    let (scheme, _) = uri
+        .split_once("://")
+        .with_context(|| format!("{:?} is no URI", uri))?;
+    if !SUPPORTED_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) {
+        Err(anyhow!("Unsupported scheme {:?} in URI {:?}", scheme, uri))
+    } else if uri.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        Err(anyhow!("Invalid characters in URI {:?}", uri))
+    } else {
+        Ok(())
+    }
+}
+
+/// Build a command line to launch the `exec` line of a desktop file with `flags` and `files`.
+///
+/// If `exec` forwards files into a Flatpak sandbox with `--file-forwarding`, wrap `files` in
+/// `@@` markers, so that Flatpak exports them through the document portal if the sandbox can't
+/// access them otherwise.
+fn build_commandline(exec: &str, flags: &[&str], files: &[&str]) -> String {
+    let mut commandline = strip_field_codes(exec);
+    for flag in flags {
+        commandline.push(' ');
+        commandline.push_str(&shell_quote(flag));
+    }
+    let file_forwarding = exec
+        .split_whitespace()
+        .any(|arg| arg == "--file-forwarding");
+    if file_forwarding && !files.is_empty() {
+        commandline.push_str(" @@");
+    }
+    for file in files {
+        commandline.push(' ');
+        commandline.push_str(&shell_quote(file));
+    }
+    if file_forwarding && !files.is_empty() {
+        commandline.push_str(" @@");
+    }
+    commandline
+}
+
+/// Describe how long ago something happened `age` ago, e.g. `opened 2 days ago`.
+fn describe_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    let (count, unit) = match minutes {
+        0 => return "opened just now".to_string(),
+        1..=59 => (minutes, "minute"),
+        60..=1439 => (minutes / 60, "hour"),
+        1440..=43199 => (minutes / 1440, "day"),
+        43200..=525_599 => (minutes / 43200, "month"),
+        _ => (minutes / 525_600, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("opened {} {}{} ago", count, unit, plural)
+}
+
+/// The directories containing `path`, innermost first.
+fn parent_segments(path: &str) -> Vec<String> {
+    let mut segments: Vec<String> = Path::new(path)
+        .parent()
+        .map(|parent| {
+            parent
+                .iter()
+                .filter(|segment| *segment != "/")
+                .map(|segment| segment.to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    segments.reverse();
+    segments
+}
+
+/// Disambiguate duplicate names among `items`, given as pairs of name and path.
+///
+/// Append as many parent directories to duplicate names as needed to tell them apart, e.g.
+/// `api — acme` and `api — billing` for two `api` directories.  Leave unique names and items
+/// without path as they are.
+fn disambiguate_names(items: &[(&str, Option<&str>)]) -> Vec<String> {
+    let mut names: Vec<String> = items.iter().map(|(name, _)| name.to_string()).collect();
+    let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, (name, _)) in items.iter().enumerate() {
+        groups.entry(*name).or_default().push(index);
+    }
+    for indices in groups.values().filter(|indices| 1 < indices.len()) {
+        let parents: Vec<(usize, Vec<String>)> = indices
+            .iter()
+            .filter_map(|index| items[*index].1.map(|path| (*index, parent_segments(path))))
+            .collect();
+        let max_depth = parents.iter().map(|(_, p)| p.len()).max().unwrap_or(0);
+        let labels_at = |depth: usize| -> Vec<String> {
+            parents
+                .iter()
+                .map(|(_, segments)| {
+                    let mut label: Vec<&str> =
+                        segments.iter().take(depth).map(String::as_str).collect();
+                    label.reverse();
+                    label.join("/")
+                })
+                .collect()
+        };
+        let labels = (1..=max_depth)
+            .map(labels_at)
+            .find(|labels| labels.iter().collect::<HashSet<_>>().len() == labels.len())
+            .unwrap_or_else(|| labels_at(max_depth));
+        for ((index, _), label) in parents.iter().zip(labels) {
+            if !label.is_empty() {
+                names[*index] = format!("{} — {}", items[*index].0, label);
+            }
+        }
+    }
+    names
+}
+
+/// The texts of a workspace to match search terms against, folded for matching.
+#[derive(Debug)]
+struct MatchTexts {
+    /// The name of the workspace, and the segments of its path below the home directory.
+    names: Vec<FoldedText>,
+    /// The full path of the workspace, if any.
+    path: Option<FoldedText>,
+    /// The last two segments of the path of the workspace, if any.
+    suffix: Option<FoldedText>,
+    /// The host and repository of the `origin` remote of the workspace, if any.
+    git_remote: Vec<FoldedText>,
+}
+
+impl MatchTexts {
+    /// Fold all texts of `item`.
+    fn new(item: &WorkspaceItem) -> Self {
+        Self {
+            names: std::iter::once(&item.name)
+                .chain(&item.segments)
+                .map(|text| FoldedText::new(text))
+                .collect(),
+            path: item.path.as_deref().map(FoldedText::new),
+            suffix: item.suffix.as_deref().map(FoldedText::new),
+            git_remote: git_remote_texts(item).map(FoldedText::new).collect(),
+        }
+    }
+
+    /// Whether these are the texts of `item`, i.e. whether we can match `item` with these texts.
+    fn is_for(&self, item: &WorkspaceItem) -> bool {
+        self.names.len() == 1 + item.segments.len()
+            && self
+                .names
+                .iter()
+                .map(FoldedText::as_str)
+                .eq(std::iter::once(&item.name)
+                    .chain(&item.segments)
+                    .map(String::as_str))
+            && self.path.as_ref().map(FoldedText::as_str) == item.path.as_deref()
+            && self.suffix.as_ref().map(FoldedText::as_str) == item.suffix.as_deref()
+            && self
+                .git_remote
+                .iter()
+                .map(FoldedText::as_str)
+                .eq(git_remote_texts(item))
+    }
+}
+
+/// The host and repository of the `origin` remote of `item`, if any.
+fn git_remote_texts(item: &WorkspaceItem) -> impl Iterator<Item = &str> {
+    item.git_remote
+        .iter()
+        .flat_map(|remote| [remote.host.as_str(), remote.repository.as_str()])
+}
+
+/// The state of the last search, to refine it efficiently.
+#[derive(Debug, Default)]
+struct LastSearch {
+    /// The IDs returned to Gnome Shell, i.e. the previous results of a refined search.
+    results: Vec<Arc<str>>,
+    /// The IDs of all matching workspaces, before limiting the number of results.
+    ///
+    /// `None` if the search terms were too short to search or the search took too long, i.e.
+    /// every workspace may match refined terms.
+    matches: Option<Vec<Arc<str>>>,
+}
+
+/// A search provider for recent workspaces of an app.
+pub struct WorkspaceSearchProvider {
+    /// The app to open workspaces with.
+    app: gio::DesktopAppInfo,
+    /// The source of workspaces.
+    source: Box<dyn WorkspacesSource>,
+    /// Workspaces found in the last search, shared with the source.
+    items: Arc<Workspaces>,
+    /// The result to clone a repository which the last search offered, if any.
+    clone_result: Option<(Arc<str>, WorkspaceItem)>,
+    /// Folded texts of all workspaces in `items`, by ID.
+    match_texts: HashMap<Arc<str>, MatchTexts>,
+    /// The context to launch the app in.
+    launch_context: gio::AppLaunchContext,
+    /// Settings for searching.
+    settings: Settings,
+    /// Workspaces launched from search results, to rank often launched workspaces first.
+    history: LaunchHistory,
+    /// The last search, to refine.
+    last_search: LastSearch,
+    /// Names of the app for `editor:` filters, in lowercase.
+    editor_names: Vec<String>,
+    /// Current branches of git repositories, for descriptions.
+    branches: BranchCache,
+    /// Hides workspaces which providers of preferred editors show already.
+    dedup: Deduplicator,
+    /// Names stored in `.code-workspace` files.
+    workspace_names: CodeWorkspaceNames,
+    /// Information about projects in workspaces, for descriptions.
+    projects: ProjectCache,
+    /// The `settings.json` of the app, if any, to respect its window settings.
+    editor_settings: Option<PathBuf>,
+    /// Records requests, to quit the service when idle.
+    activity: Activity,
+    /// Generations of searches, to cancel searches which a newer search superseded.
+    search_generation: SearchGeneration,
+    /// The environment of the graphical session to launch apps in, if any.
+    session_environment: Option<SessionEnvironment>,
+}
+
+impl WorkspaceSearchProvider {
+    /// Create a new search provider for workspaces from `source`, opened with `app`.
+    ///
+    /// Record launched workspaces in `history`, and hide workspaces which `dedup` marks as
+    /// duplicates of other providers.
+    pub fn new<S>(
+        app: gio::DesktopAppInfo,
+        source: S,
+        launch_context: gio::AppLaunchContext,
+        settings: Settings,
+        history: LaunchHistory,
+        dedup: Deduplicator,
+    ) -> Self
+    where
+        S: WorkspacesSource + 'static,
+    {
+        let mut editor_names = Vec::new();
+        if let Some(id) = app.id() {
+            let id = id.trim_end_matches(".desktop").to_lowercase();
+            // Also accept the last component of reverse DNS IDs, e.g. `codium` for
+            // `com.vscodium.codium`
+            editor_names.extend(id.rsplit('.').next().map(str::to_string));
+            editor_names.push(id);
+        }
+        editor_names.extend(
+            app.executable()
+                .file_name()
+                .map(|name| name.to_string_lossy().to_lowercase()),
+        );
+        Self {
+            editor_names,
+            app,
+            source: Box::new(source),
+            items: Arc::default(),
+            clone_result: None,
+            match_texts: HashMap::new(),
+            launch_context,
+            settings,
+            history,
+            last_search: LastSearch::default(),
+            branches: BranchCache::default(),
+            dedup,
+            workspace_names: CodeWorkspaceNames::default(),
+            projects: ProjectCache::default(),
+            editor_settings: None,
+            activity: Activity::default(),
+            search_generation: SearchGeneration::default(),
+            session_environment: None,
+        }
+    }
+
+    /// Respect the window settings in the `settings.json` of the app at `path`.
+    pub fn with_editor_settings(self, path: Option<PathBuf>) -> Self {
+        Self {
+            editor_settings: path,
+            ..self
+        }
+    }
+
+    /// Record requests to this provider in `activity`.
+    pub fn with_activity(self, activity: Activity) -> Self {
+        Self { activity, ..self }
+    }
+
+    /// Cancel searches once a newer search arrives, according to `search_generation`.
+    pub fn with_search_generation(self, search_generation: SearchGeneration) -> Self {
+        Self {
+            search_generation,
+            ..self
+        }
+    }
+
+    /// Refresh `session_environment` on every search, to launch apps in the current session.
+    pub fn with_session_environment(self, session_environment: SessionEnvironment) -> Self {
+        Self {
+            session_environment: Some(session_environment),
+            ..self
+        }
+    }
+
+    /// The window mode in the `settings.json` of the app for targets of the given `kind`, if any.
+    ///
+    /// `window.openFoldersInNewWindow` only applies to folders and workspaces, so return `None`
+    /// for files.  Read `settings.json` on every launch, because users don't expect to restart
+    /// this service after changing settings of their editor.
+    fn editor_window_mode(&self, kind: RecentKind) -> Option<WindowMode> {
+        if kind == RecentKind::File {
+            return None;
+        }
+        let path = self.editor_settings.as_ref()?;
+        trace!("Reading window settings from {}", path.display());
+        match std::fs::read_to_string(path) {
+            Ok(contents) => WindowMode::from_editor_settings(&contents),
+            Err(error) => {
+                debug!("Failed to read {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+
+    /// The path of `item` if it's safe to access, i.e. local and not on a network mount.
+    fn local_path(item: &WorkspaceItem) -> Option<&Path> {
+        item.path
+            .as_deref()
+            .filter(|_| !item.remote && !item.on_network_mount)
+            .map(Path::new)
+    }
+
+    /// The icon for results of this provider.
+    ///
+    /// Use the icon from settings for the app if any, and the icon of the app otherwise.
+    fn icon(&self) -> Option<gio::Icon> {
+        let configured = self
+            .app
+            .id()
+            .and_then(|id| self.settings.icons.get(id.as_str()));
+        match configured {
+            Some(icon) if Path::new(icon).is_absolute() => {
+                Some(gio::FileIcon::new(&gio::File::for_path(icon)).upcast())
+            }
+            Some(icon) => Some(gio::ThemedIcon::new(icon).upcast()),
+            None => self.app.icon().map(prefer_themed_icon),
+        }
+    }
+
+    /// Fold the texts of all workspaces for matching.
+    ///
+    /// Do this once when workspaces change, instead of for every search: keep the texts of all
+    /// workspaces which didn't change, and only fold texts of new or changed workspaces.
+    fn fold_match_texts(&mut self) {
+        let items = &self.items;
+        self.match_texts
+            .retain(|id, texts| items.get(id).map_or(false, |item| texts.is_for(item)));
+        for (id, item) in items.iter() {
+            if !self.match_texts.contains_key(id) {
+                self.match_texts.insert(id.clone(), MatchTexts::new(item));
+            }
+        }
+    }
+
+    /// Whether `item` passes all `filters`.
+    fn matches_filters(&self, item: &WorkspaceItem, filters: &[Filter]) -> bool {
+        filters.iter().all(|filter| match filter {
+            Filter::Remote => item.remote,
+            Filter::File => item.kind == RecentKind::File,
+            Filter::Editor(name) => self.editor_names.contains(name),
+            Filter::Terminal => !item.remote && item.path.is_some(),
+        })
+    }
+
+    /// Find the IDs of all `ids` whose items match `terms`, best match first.
+    ///
+    /// Apply filter prefixes in `terms` first, and match the remaining terms.
+    ///
+    /// Stop matching at `deadline` or once `token` is cancelled, and return whether all `ids` were
+    /// matched along with the matching IDs.
+    fn find_matching_ids<'a, I: IntoIterator<Item = &'a Arc<str>>>(
+        &self,
+        ids: I,
+        terms: &[String],
+        deadline: Instant,
+        token: &CancellationToken,
+    ) -> (Vec<Arc<str>>, bool) {
+        let (filters, terms) = parse_filters(&split_terms(terms));
+        let match_suffix = terms.iter().any(|term| term.contains('/'));
+        let matcher = self.settings.matcher.matcher(&terms);
+        // With an editor filter other editors show no results, so don't hide workspaces they have
+        let hide_duplicates = !filters
+            .iter()
+            .any(|filter| matches!(filter, Filter::Editor(_)));
+        let now = now();
+        let complete = Cell::new(true);
+        let in_time = |_: &&Arc<str>| {
+            complete.set(Instant::now() < deadline && !token.is_cancelled());
+            complete.get()
+        };
+        let candidates = ids.into_iter().take_while(in_time).filter_map(|id| {
+            let item = self
+                .items
+                .get(id)
+                .filter(|item| self.matches_filters(item, &filters))
+                .filter(|item| !(hide_duplicates && self.dedup.is_duplicate(&item.url)))?;
+            self.match_texts.get(id).map(|match_texts| {
+                let mut texts: Vec<&FoldedText> = match_texts.names.iter().collect();
+                if self.settings.match_path {
+                    texts.extend(&match_texts.path);
+                }
+                if match_suffix {
+                    texts.extend(&match_texts.suffix);
+                }
+                texts.extend(&match_texts.git_remote);
+                Candidate {
+                    id,
+                    texts,
+                    recency: item.recency,
+                    frecency: self.history.frecency(id, now),
+                }
+            })
+        });
+        let matches = rank_matches(matcher.as_ref(), candidates);
+        (matches, complete.get())
+    }
+
+    /// A result to clone the repository which `terms` refer to, if any.
+    ///
+    /// Only offer to clone if settings have a directory to clone to, `terms` refer to a git
+    /// repository, and the repository doesn't exist in the directory already.  Permit only
+    /// editor filters for this app in `terms`.
+    fn clone_item(&self, terms: &[String]) -> Option<(Arc<str>, WorkspaceItem)> {
+        let clone_dir = self.settings.clone_dir.as_deref()?;
+        let (filters, terms) = parse_filters(&split_terms(terms));
+        let only_this_editor = filters.iter().all(|filter| match filter {
+            Filter::Editor(name) => self.editor_names.contains(name),
+            _ => false,
+        });
+        let query = match terms.as_slice() {
+            [query] if only_this_editor => query,
+            _ => return None,
+        };
+        let (url, remote) = clone_url(query)?;
+        let url: Arc<str> = url.into();
+        let name = remote.repository.rsplit('/').next()?.to_string();
+        let clone_dir = match clone_dir.strip_prefix("~/") {
+            Some(rest) => gio::glib::home_dir().join(rest),
+            None => PathBuf::from(clone_dir),
+        };
+        let directory = clone_dir.join(&name);
+        if directory.exists() {
+            return None;
+        }
+        let item = WorkspaceItem {
+            name: format!("Clone {}", remote.repository),
+            description: Some(format!("{} → {}", url, directory.display())),
+            url: url.clone(),
+            target: WorkspaceTarget::Clone { url, directory },
+            path: None,
+            segments: Vec::new(),
+            suffix: None,
+            recency: 0,
+            kind: RecentKind::Folder,
+            remote: false,
+            git_remote: Some(remote),
+            last_opened: None,
+            profile: None,
+            workspace_file: None,
+            canonical_path: None,
+            on_network_mount: false,
+        };
+        let id = format!("{}{}", CLONE_ID_PREFIX, item.target.uri());
+        Some((id.into(), item))
+    }
+
+    /// Whether `terms` have fewer characters than the minimum query length from settings.
+    ///
+    /// Do not count whitespace and filter prefixes.
+    fn is_query_too_short(&self, terms: &[String]) -> bool {
+        let (_, terms) = parse_filters(&split_terms(terms));
+        let length: usize = terms
+            .iter()
+            .map(|term| term.chars().filter(|c| !c.is_whitespace()).count())
+            .sum();
+        length < self.settings.min_query_length
+    }
+
+    /// Search `candidates` for `terms`, or all workspaces if `candidates` is `None`.
+    ///
+    /// Remember the search to refine it later, and return the IDs of matching workspaces.  Return
+    /// the workspaces matched until `deadline` if the search takes longer.
+    ///
+    /// Stop searching and return no workspaces once `token` is cancelled, because Gnome Shell
+    /// drops the results of a search once it sent a newer one.
+    fn search(
+        &mut self,
+        candidates: Option<Vec<Arc<str>>>,
+        terms: &[String],
+        deadline: Instant,
+        token: &CancellationToken,
+    ) -> Vec<Arc<str>> {
+        if self.is_query_too_short(terms) {
+            debug!("Search terms {:?} too short, skipping search", terms);
+            self.last_search = LastSearch::default();
+            return Vec::new();
+        }
+        // Clone results are only for the terms they were made for
+        self.clone_result = None;
+        let (mut matches, complete) = match candidates {
+            Some(candidates) => self.find_matching_ids(&candidates, terms, deadline, token),
+            None => self.find_matching_ids(self.items.keys(), terms, deadline, token),
+        };
+        if token.is_cancelled() {
+            debug!("Search for {:?} superseded by a newer search", terms);
+            self.last_search = LastSearch::default();
+            return Vec::new();
+        }
+        if !complete {
+            warn!(
+                "Search for {:?} took longer than {:?}, returning {} partial match(es)",
+                terms,
+                SEARCH_BUDGET,
+                matches.len()
+            );
+        }
+        if matches.is_empty() && complete {
+            if let Some((id, item)) = self.clone_item(terms) {
+                debug!("Offering to clone {}", item.target.uri());
+                matches.push(id.clone());
+                self.clone_result = Some((id, item));
+            }
+        }
+        debug!(
+            "Found {} matching workspace(s) for {:?}",
+            matches.len(),
+            terms
+        );
+        let results = self.limit_results(matches.clone());
+        self.last_search = LastSearch {
+            results: results.clone(),
+            // Refine partial searches from scratch, because unmatched workspaces may still match
+            matches: Some(matches).filter(|_| complete),
+        };
+        results
+    }
+
+    /// Limit `ids` to the maximum number of results from settings, if any.
+    fn limit_results(&self, mut ids: Vec<Arc<str>>) -> Vec<Arc<str>> {
+        if let Some(max_results) = self.settings.max_results {
+            ids.truncate(max_results);
+        }
+        ids
+    }
+
+    /// Launch the app with additional `flags` and `files` on its command line, for a user event at
+    /// `timestamp`.
+    fn launch_with_args(&self, flags: &[&str], files: &[&str], timestamp: u32) -> Result<()> {
+        let exec = self
+            .app
+            .commandline()
+            .with_context(|| format!("App {:?} has no command line", self.app.id()))?;
+        let commandline = build_commandline(&exec.to_string_lossy(), flags, files);
+        debug!("Launching {}", commandline);
+        let app = gio::AppInfo::create_from_commandline(
+            &commandline,
+            Some(self.app.name().as_str()),
+            gio::AppInfoCreateFlags::NONE,
+        )?;
+        self.with_launch_context(timestamp, |context| app.launch(&[], Some(context)))?;
+        Ok(())
+    }
+
+    /// Call `launch` with a launch context which passes a new startup ID and the environment of the
+    /// current graphical session to the app.
+    ///
+    /// `timestamp` is the time of the user event which caused the launch.
+    fn with_launch_context<T>(
+        &self,
+        timestamp: u32,
+        launch: impl FnOnce(&gio::AppLaunchContext) -> T,
+    ) -> T {
+        let app_id = self
+            .app
+            .id()
+            .map_or_else(|| self.app.name().to_string(), |id| id.to_string());
+        let id = next_startup_id(&app_id, timestamp);
+        debug!("Launching with startup ID {}", id);
+        set_startup_id(&self.launch_context, &id);
+        let result = launch(&self.launch_context);
+        unset_startup_id(&self.launch_context);
+        result
+    }
+
+    /// Open the given `target` of the given `kind` for a user event at `timestamp`.
+    ///
+    /// Ask the editor for a new window or to reuse its last window, as configured.
+    fn launch_target(
+        &self,
+        target: &WorkspaceTarget,
+        kind: RecentKind,
+        timestamp: u32,
+    ) -> Result<()> {
+        match target {
+            WorkspaceTarget::Clone { url, .. } if !is_clone_url(url) => {
+                return Err(anyhow!("Refusing to clone from {:?}", url));
+            }
+            WorkspaceTarget::Clone { .. } => {}
+            _ => validate_uri(target.uri())?,
+        }
+        let window_args = self
+            .app
+            .id()
+            .map_or(self.settings.window, |id| {
+                self.settings
+                    .window_mode(&id, self.editor_window_mode(kind))
+            })
+            .args();
+        match target {
+            WorkspaceTarget::Uri(uri) => {
+                let path = gio::File::for_uri(uri)
+                    .path()
+                    .filter(|_| !window_args.is_empty());
+                match path {
+                    Some(path) => {
+                        let path = path.to_string_lossy();
+                        self.launch_with_args(window_args, &[&*path], timestamp)
+                    }
+                    None => self
+                        .with_launch_context(timestamp, |context| {
+                            self.app.launch_uris(&[&**uri], Some(context))
+                        })
+                        .map_err(Into::into),
+                }
+                .with_context(|| format!("Failed to launch app with {}", uri))
+            }
+            WorkspaceTarget::RemoteFolder(uri) => self
+                .launch_with_args(
+                    &[window_args, &["--folder-uri", &**uri]].concat(),
+                    &[],
+                    timestamp,
+                )
+                .with_context(|| format!("Failed to open remote folder {}", uri)),
+            WorkspaceTarget::FileUri(uri) => self
+                .launch_with_args(
+                    &[window_args, &["--file-uri", &**uri]].concat(),
+                    &[],
+                    timestamp,
+                )
+                .with_context(|| format!("Failed to open file {}", uri)),
+            WorkspaceTarget::Clone { url, directory } => self
+                .clone_and_launch(url, directory, window_args, timestamp)
+                .with_context(|| format!("Failed to clone {}", url)),
+        }
+    }
+
+    /// Clone the git repository at `url` into `directory`, and open `directory` afterwards.
+    ///
+    /// Clone in the background, to return to Gnome Shell immediately.  Open `directory` with
+    /// `window_args`, for a user event at `timestamp`.
+    fn clone_and_launch(
+        &self,
+        url: &str,
+        directory: &Path,
+        window_args: &[&str],
+        timestamp: u32,
+    ) -> Result<()> {
+        let exec = self
+            .app
+            .commandline()
+            .with_context(|| format!("App {:?} has no command line", self.app.id()))?;
+        let directory = directory.to_string_lossy();
+        let editor = build_commandline(&exec.to_string_lossy(), window_args, &[&directory]);
+        let commandline = format!(
+            "sh -c {} clone {} {} {}",
+            shell_quote(r#"git clone -- "$1" "$2" && shift 2 && exec "$@""#),
+            shell_quote(url),
+            shell_quote(&directory),
+            editor
+        );
+        debug!("Launching {}", commandline);
+        let app = gio::AppInfo::create_from_commandline(
+            &commandline,
+            Some(self.app.name().as_str()),
+            gio::AppInfoCreateFlags::NONE,
+        )?;
+        self.with_launch_context(timestamp, |context| app.launch(&[], Some(context)))?;
+        Ok(())
+    }
+
+    /// Open a terminal in the directory of `item`, for a user event at `timestamp`.
+    ///
+    /// Open recent files and workspace files in their parent directory.
+    fn launch_in_terminal(&self, item: &WorkspaceItem, timestamp: u32) -> Result<()> {
+        let path = Path::new(
+            item.path
+                .as_deref()
+                .filter(|_| !item.remote)
+                .with_context(|| format!("{} is no local workspace", item.name))?,
+        );
+        let directory = match item.kind {
+            RecentKind::Folder => Some(path),
+            RecentKind::File | RecentKind::Workspace => path.parent(),
+        }
+        .filter(|directory| directory.is_dir())
+        .with_context(|| format!("No directory for {}", path.display()))?;
+        let terminal = match &self.settings.terminal {
+            Some(terminal) => terminal.clone(),
+            None => TERMINALS
+                .iter()
+                .find(|terminal| gio::glib::find_program_in_path(terminal).is_some())
+                .map(|terminal| format!("{} --working-directory", terminal))
+                .with_context(|| "No terminal found, set terminal in settings")?,
+        };
+        let commandline = format!("{} {}", terminal, shell_quote(&directory.to_string_lossy()));
+        debug!("Launching {}", commandline);
+        let app = gio::AppInfo::create_from_commandline(
+            &commandline,
+            Some("Terminal"),
+            gio::AppInfoCreateFlags::NONE,
+        )?;
+        self.with_launch_context(timestamp, |context| app.launch(&[], Some(context)))?;
+        Ok(())
+    }
+}
+
+/// The path search `terms` spell out, if any.
+///
+/// Ignore filter prefixes, and expand `~/` to `home_dir`.  Return `None` for terms which are no
+/// absolute path.
+fn search_path(terms: &[String], home_dir: &Path) -> Option<PathBuf> {
+    let (_, terms) = parse_filters(terms);
+    let query = terms.join(" ");
+    let path = match query.strip_prefix("~/") {
+        Some(rest) => home_dir.join(rest),
+        None => PathBuf::from(query),
+    };
+    Some(path).filter(|path| path.is_absolute())
+}
+
+/// The DBus interface of Gnome Shell search providers.
+///
+/// See <https://developer.gnome.org/SearchProvider/>.
+///
+/// Gnome Shell sends a search for every keystroke; the service skips searches which a newer
+/// search supersedes before they start, see `serve`.
+#[dbus_interface(name = "org.gnome.Shell.SearchProvider2")]
+impl WorkspaceSearchProvider {
+    /// Starts a search.
+    ///
+    /// Refresh all workspaces and the environment of the session, and return the IDs of all
+    /// workspaces matching `terms`.
+    ///
+    /// Return the matches found so far if matching takes longer than `SEARCH_BUDGET`.  Only
+    /// matching counts: finding workspaces only blocks if we have no workspaces at all yet, and
+    /// their paths and git remotes are resolved when reading them, so a deadline for all of
+    /// this would leave no time for matching and return an empty partial answer.
+    fn get_initial_result_set(&mut self, terms: Vec<String>) -> Vec<String> {
+        self.activity.touch();
+        let token = self.search_generation.token();
+        debug!("Searching for {:?}", terms);
+        // Users likely open a result of this search, so get the environment ready meanwhile
+        if let Some(session_environment) = &self.session_environment {
+            session_environment.refresh();
+        }
+        match self.source.find_workspaces() {
+            // Only fold texts again if the source found new workspaces
+            Ok(items) if !Arc::ptr_eq(&items, &self.items) => {
+                self.items = items;
+                self.fold_match_texts();
+            }
+            Ok(_) => {}
+            Err(error) => error!("Failed to find recent workspaces: {:#}", error),
+        }
+        result_ids(self.search(None, &terms, Instant::now() + SEARCH_BUDGET, &token))
+    }
+
+    /// Refine an ongoing search.
+    ///
+    /// Return the IDs of all workspaces which matched the previous search and still match
+    /// `terms`, without reading workspaces again.
+    ///
+    /// If `previous_results` are the results of the last search, refine all matches of the last
+    /// search, which may include matches beyond the maximum number of results.  Otherwise refine
+    /// `previous_results`, or search all workspaces again if the number of results is limited.
+    fn get_subsearch_result_set(
+        &mut self,
+        previous_results: Vec<String>,
+        terms: Vec<String>,
+    ) -> Vec<String> {
+        self.activity.touch();
+        let token = self.search_generation.token();
+        debug!("Refining search for {:?}", terms);
+        let last_search = std::mem::take(&mut self.last_search);
+        let same_results = last_search
+            .results
+            .iter()
+            .map(|id| &**id)
+            .eq(previous_results.iter().map(String::as_str));
+        let candidates = if same_results {
+            last_search.matches
+        } else if self.settings.max_results.is_some() {
+            None
+        } else {
+            Some(
+                previous_results
+                    .iter()
+                    .filter_map(|id| self.items.get_key_value(id.as_str()))
+                    .map(|(id, _)| id.clone())
+                    .collect(),
+            )
+        };
+        result_ids(self.search(candidates, &terms, Instant::now() + SEARCH_BUDGET, &token))
+    }
+
+    /// Get metadata for the given `results`.
+    ///
+    /// Take names of multi-root workspaces from their `.code-workspace` file, if it has one.
+    ///
+    /// Describe workspaces by the language of their project, their location, the current branch
+    /// of local git repositories and the time the workspace was last opened, and optionally the
+    /// title of their README.
+    ///
+    /// Mark remote workspaces with an emblem.
+    fn get_result_metas(&mut self, results: Vec<String>) -> Vec<HashMap<String, Value<'static>>> {
+        self.activity.touch();
+        trace!("Getting metadata for {:?}", results);
+        let icon = self.icon();
+        let gicon = icon
+            .as_ref()
+            .and_then(IconExt::to_string)
+            .map(|icon| icon.to_string());
+        // Mark remote workspaces with a network emblem
+        let remote_gicon = icon.as_ref().and_then(|icon| {
+            let emblem = gio::Emblem::new(&gio::ThemedIcon::new(REMOTE_EMBLEM));
+            IconExt::to_string(&gio::EmblemedIcon::new(icon, Some(&emblem)))
+                .map(|icon| icon.to_string())
+        });
+        let (all_items, clone_result) = (&self.items, self.clone_result.as_ref());
+        let (branches, projects) = (&mut self.branches, &mut self.projects);
+        let show_readme_title = self.settings.show_readme_title;
+        let workspace_names = &mut self.workspace_names;
+        let now = SystemTime::now();
+        let items: Vec<(String, &WorkspaceItem)> = results
+            .into_iter()
+            .filter_map(|id| find_item(all_items, clone_result, &id).map(|item| (id, item)))
+            .collect();
+        let names: Vec<String> = items
+            .iter()
+            .map(|(_, item)| {
+                let name = item
+                    .workspace_file
+                    .as_deref()
+                    .filter(|_| !item.on_network_mount)
+                    .map(Path::new)
+                    .and_then(|path| workspace_names.name(path));
+                match (name, &item.profile) {
+                    (Some(name), Some(profile)) => format!("{} ({})", name, profile),
+                    (Some(name), None) => name,
+                    (None, _) => item.name.clone(),
+                }
+            })
+            .collect();
+        let names = disambiguate_names(
+            &items
+                .iter()
+                .zip(&names)
+                .map(|((_, item), name)| (name.as_str(), item.path.as_deref()))
+                .collect::<Vec<_>>(),
+        );
+        items
+            .into_iter()
+            .zip(names)
+            .map(|((id, item), name)| {
+                let mut meta = HashMap::new();
+                meta.insert("name".to_string(), name.into());
+                let local_path = Self::local_path(item);
+                let branch = local_path.and_then(|path| branches.branch(path));
+                let opened = item
+                    .last_opened
+                    .and_then(|time| now.duration_since(time).ok())
+                    .map(describe_age);
+                let title = local_path
+                    .filter(|_| show_readme_title)
+                    .and_then(|path| projects.readme_title(path));
+                let language = local_path
+                    .and_then(|path| projects.language(path))
+                    .map(str::to_string);
+                let parts: Vec<String> = title
+                    .into_iter()
+                    .chain(language)
+                    .chain(item.description.iter().cloned())
+                    .chain(branch)
+                    .chain(opened)
+                    .collect();
+                if !parts.is_empty() {
+                    meta.insert("description".to_string(), parts.join(" — ").into());
+                }
+                let gicon = if item.remote {
+                    remote_gicon.as_ref().or_else(|| gicon.as_ref())
+                } else {
+                    gicon.as_ref()
+                };
+                if let Some(gicon) = gicon {
+                    meta.insert("gicon".to_string(), gicon.clone().into());
+                }
+                meta.insert("id".to_string(), id.into());
+                meta
+            })
+            .collect()
+    }
+
+    /// Activate the result with the given `id`.
+    ///
+    /// Record the launch in the launch history.
+    fn activate_result(
+        &mut self,
+        id: String,
+        terms: Vec<String>,
+        timestamp: u32,
+    ) -> zbus::fdo::Result<()> {
+        self.activity.touch();
+        debug!("Activating {} for {:?} at {}", id, terms, timestamp);
+        let item = find_item(&self.items, self.clone_result.as_ref(), &id)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Unknown result {}", id)))?;
+        let (filters, _) = parse_filters(&split_terms(&terms));
+        let in_terminal = filters.contains(&Filter::Terminal)
+            || (self.settings.open_in_terminal && !item.remote && item.path.is_some());
+        let result = if in_terminal {
+            info!("Opening {} in terminal", item.name);
+            self.launch_in_terminal(item, timestamp)
+        } else {
+            info!("Opening {} ({:?})", item.name, item.target);
+            self.launch_target(&item.target, item.kind, timestamp)
+        };
+        result.map_err(|error| {
+            error!("Failed to open {}: {:#}", item.name, error);
+            notify_failure(
+                &self.app.name(),
+                &format!("Failed to open {}", item.name),
+                &format!("{:#}", error),
+            );
+            zbus::fdo::Error::Failed(format!("{:#}", error))
+        })?;
+        if !id.starts_with(CLONE_ID_PREFIX) {
+            self.history.record(&id, now());
+            if let Err(error) = self.history.save() {
+                warn!("Failed to save launch history: {:#}", error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Launch the app for a search.
+    ///
+    /// VSCode has no command line flag to search its recent workspaces, so we can't forward
+    /// arbitrary search terms.  If the search terms spell out an existing path, e.g.
+    /// `~/Code/mdcat`, open this path; otherwise just launch the app.
+    fn launch_search(&self, terms: Vec<String>, timestamp: u32) -> zbus::fdo::Result<()> {
+        self.activity.touch();
+        debug!("Launching app for {:?} at {}", terms, timestamp);
+        let path = search_path(&terms, &gio::glib::home_dir()).filter(|path| path.exists());
+        let result = match path {
+            Some(path) => {
+                info!("Opening {} for search", path.display());
+                let target = WorkspaceTarget::Uri(gio::File::for_path(&path).uri().as_str().into());
+                let kind = if path.is_dir() {
+                    RecentKind::Folder
+                } else if path.to_string_lossy().ends_with(CODE_WORKSPACE_EXTENSION) {
+                    RecentKind::Workspace
+                } else {
+                    RecentKind::File
+                };
+                self.launch_target(&target, kind, timestamp)
+            }
+            None => self
+                .with_launch_context(timestamp, |context| self.app.launch(&[], Some(context)))
+                .map_err(Into::into),
+        };
+        result.map_err(|error| {
+            error!("Failed to launch app: {:#}", error);
+            notify_failure(
+                &self.app.name(),
+                &format!("Failed to launch {}", self.app.name()),
+                &format!("{:#}", error),
+            );
+            zbus::fdo::Error::SpawnFailed(format!("{:#}", error))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn search_paths() {
+        let home = Path::new("/home/foo");
+        assert_eq!(
+            search_path(&terms(&["~/Code/mdcat"]), home),
+            Some(PathBuf::from("/home/foo/Code/mdcat"))
+        );
+        assert_eq!(
+            search_path(&terms(&["codium:", "/srv/my", "project"]), home),
+            Some(PathBuf::from("/srv/my project"))
+        );
+        assert_eq!(search_path(&terms(&["mdcat"]), home), None);
+    }
+
+    #[test]
+    fn theme_icon_names() {
+        assert_eq!(
+            theme_icon_name(Path::new("/usr/share/icons/hicolor/256x256/apps/code.png")).as_deref(),
+            Some("code")
+        );
+        assert_eq!(
+            theme_icon_name(Path::new("/opt/visual-studio-code/resources/code.png")),
+            None
+        );
+    }
+
+    #[test]
+    fn describe_ages() {
+        assert_eq!(describe_age(Duration::from_secs(30)), "opened just now");
+        assert_eq!(describe_age(Duration::from_secs(60)), "opened 1 minute ago");
+        assert_eq!(
+            describe_age(Duration::from_secs(3 * 3600)),
+            "opened 3 hours ago"
+        );
+        assert_eq!(
+            describe_age(Duration::from_secs(2 * 86400 + 100)),
+            "opened 2 days ago"
+        );
+        assert_eq!(
+            describe_age(Duration::from_secs(400 * 86400)),
+            "opened 1 year ago"
+        );
+    }
+
+    #[test]
+    fn disambiguate_duplicate_names() {
+        let items = [
+            ("api", Some("/home/foo/work/acme/api")),
+            ("mdcat", Some("/home/foo/mdcat")),
+            ("api", Some("/home/foo/work/billing/api")),
+            ("api", Some("/home/foo/play/billing/api")),
+            ("api", None),
+        ];
+        assert_eq!(
+            disambiguate_names(&items),
+            vec![
+                "api — work/acme",
+                "mdcat",
+                "api — work/billing",
+                "api — play/billing",
+                "api"
+            ]
+        );
+    }
+
+    #[test]
+    fn strip_field_codes_from_exec() {
+        assert_eq!(
+            strip_field_codes("/usr/bin/code --unity-launch %F"),
+            "/usr/bin/code --unity-launch"
+        );
+        assert_eq!(
+            strip_field_codes(
+                "/usr/bin/flatpak run --file-forwarding com.visualstudio.code @@ %F @@"
+            ),
+            "/usr/bin/flatpak run --file-forwarding com.visualstudio.code"
+        );
+    }
+
+    #[test]
+    fn validate_uris() {
+        assert!(validate_uri("file:///home/foo/My%20Project").is_ok());
+        assert!(validate_uri("vscode-remote://ssh-remote%2Bfoo/home/foo/mdcat").is_ok());
+        assert!(validate_uri("http://127.0.0.1:8080/?folder=/home/foo/mdcat").is_ok());
+        assert!(validate_uri("javascript://alert(1)").is_err());
+        assert!(validate_uri("file:///home/foo/a\nb").is_err());
+        assert!(validate_uri("--disable-extensions").is_err());
+    }
+
+    #[test]
+    fn build_commandlines() {
+        assert_eq!(
+            build_commandline("/usr/bin/code %F", &["--new-window"], &["/home/foo/mdcat"]),
+            "/usr/bin/code '--new-window' '/home/foo/mdcat'"
+        );
+        assert_eq!(
+            build_commandline(
+                "/usr/bin/flatpak run --file-forwarding com.vscodium.codium @@ %F @@",
+                &["--reuse-window"],
+                &["/home/foo/mdcat"]
+            ),
+            "/usr/bin/flatpak run --file-forwarding com.vscodium.codium '--reuse-window' @@ '/home/foo/mdcat' @@"
+        );
+        assert_eq!(
+            build_commandline(
+                "/usr/bin/flatpak run --file-forwarding com.vscodium.codium @@ %F @@",
+                &["--folder-uri", "vscode-remote://ssh-remote+foo/home/foo"],
+                &[]
+            ),
+            "/usr/bin/flatpak run --file-forwarding com.vscodium.codium '--folder-uri' 'vscode-remote://ssh-remote+foo/home/foo'"
+        );
+    }
+
+    #[test]
+    fn quote_for_shell() {
+        assert_eq!(
+            shell_quote("vscode-remote://ssh-remote+foo/home/foo"),
+            "'vscode-remote://ssh-remote+foo/home/foo'"
+        );
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(
+            shell_quote("vscode-remote://ssh-remote%2Bfoo/home/foo"),
+            "'vscode-remote://ssh-remote%%2Bfoo/home/foo'"
+        );
+    }
+}
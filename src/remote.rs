@@ -0,0 +1,368 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Remote workspaces of VSCode.
+
+use std::fmt::{Display, Formatter};
+
+use serde::Deserialize;
+
+/// The URL scheme of remote workspaces.
+const REMOTE_SCHEME: &str = "vscode-remote://";
+
+/// The URL scheme of virtual workspaces, e.g. remote repositories.
+const VFS_SCHEME: &str = "vscode-vfs://";
+
+/// Decode percent-encoded characters in `s`.
+///
+/// Leave invalid escapes as they are.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The path of the remote workspace at `url`, still percent-encoded.
+///
+/// Return `None` if `url` doesn't refer to a remote workspace.
+pub fn remote_path(url: &str) -> Option<&str> {
+    let rest = url.strip_prefix(REMOTE_SCHEME)?;
+    rest.find('/').map(|index| &rest[index..])
+}
+
+/// Decode a string of hex digits into a string.
+///
+/// Return `None` if `s` isn't valid hex or doesn't decode to UTF-8.
+fn hex_decode(s: &str) -> Option<String> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// The JSON metadata in the authority of dev container workspaces.
+#[derive(Debug, Deserialize)]
+struct DevContainerMetadata {
+    /// The local folder opened in the dev container.
+    #[serde(rename = "hostPath")]
+    host_path: String,
+}
+
+/// Extract the local folder from the `value` of a dev container authority.
+///
+/// VSCode encodes the local folder in hex, either directly, or as part of a JSON object in newer
+/// versions.
+fn dev_container_host_path(value: &str) -> Option<String> {
+    let decoded = hex_decode(value)?;
+    if decoded.starts_with('{') {
+        serde_json::from_str::<DevContainerMetadata>(&decoded)
+            .ok()
+            .map(|metadata| metadata.host_path)
+    } else {
+        Some(decoded)
+    }
+}
+
+/// The remote a workspace lives on.
+#[derive(Debug, PartialEq)]
+pub enum Remote {
+    /// A host accessed with Remote - SSH.
+    Ssh {
+        /// The SSH host.
+        host: String,
+    },
+    /// A distribution in the Windows Subsystem for Linux.
+    ///
+    /// These workspaces only exist on Windows, and typically show up on Linux only through
+    /// settings sync.
+    Wsl {
+        /// The WSL distribution.
+        distro: String,
+    },
+    /// A dev container for a local folder.
+    DevContainer {
+        /// The local folder opened in the container, if known.
+        host_path: Option<String>,
+    },
+    /// A machine accessed with Remote - Tunnels.
+    Tunnel {
+        /// The name of the tunnel.
+        name: String,
+    },
+    /// A GitHub Codespace.
+    Codespace {
+        /// The name of the codespace.
+        name: String,
+    },
+    /// A repository opened with Remote Repositories.
+    Repository {
+        /// The human readable name of the repository provider, e.g. `GitHub`.
+        provider: String,
+        /// The repository, e.g. `owner/repo`.
+        repository: String,
+    },
+    /// Any other kind of remote.
+    Other {
+        /// The kind of remote, e.g. `ssh-remote`.
+        kind: String,
+        /// The remote-specific value of the remote authority.
+        value: String,
+    },
+}
+
+impl Remote {
+    /// Parse the remote of the given workspace `url`.
+    ///
+    /// Return `None` if `url` doesn't refer to a remote workspace.
+    pub fn from_url(url: &str) -> Option<Self> {
+        if let Some(rest) = url.strip_prefix(VFS_SCHEME) {
+            return Some(Self::from_vfs(rest));
+        }
+        let rest = url.strip_prefix(REMOTE_SCHEME)?;
+        let authority = percent_decode(rest.split('/').next()?);
+        let (kind, value) = match authority.find('+') {
+            Some(index) => (&authority[..index], &authority[index + 1..]),
+            None => (authority.as_str(), ""),
+        };
+        let remote = match kind {
+            "ssh-remote" => Remote::Ssh {
+                host: value.to_string(),
+            },
+            "wsl" => Remote::Wsl {
+                distro: value.to_string(),
+            },
+            "dev-container" | "attached-container" => Remote::DevContainer {
+                host_path: dev_container_host_path(value),
+            },
+            "tunnel" => Remote::Tunnel {
+                name: value.to_string(),
+            },
+            "codespaces" => Remote::Codespace {
+                name: value.to_string(),
+            },
+            _ => Remote::Other {
+                kind: kind.to_string(),
+                value: value.to_string(),
+            },
+        };
+        Some(remote)
+    }
+
+    /// Parse a virtual workspace from the `rest` of its URL after the scheme.
+    ///
+    /// Remote Repositories uses URLs like `vscode-vfs://github/owner/repo`.
+    fn from_vfs(rest: &str) -> Self {
+        let mut segments = rest.split('/');
+        let authority = percent_decode(segments.next().unwrap_or_default());
+        // The authority may carry further information after a `+`
+        let authority = authority.split('+').next().unwrap_or_default();
+        let provider = match authority {
+            "github" => "GitHub".to_string(),
+            "azurerepos" => "Azure Repos".to_string(),
+            other => other.to_string(),
+        };
+        let repository = segments
+            .filter(|segment| !segment.is_empty())
+            .take(2)
+            .map(percent_decode)
+            .collect::<Vec<_>>()
+            .join("/");
+        Remote::Repository {
+            provider,
+            repository,
+        }
+    }
+
+    /// A human readable name for a workspace called `name` on this remote.
+    ///
+    /// Use the name of the local folder for dev containers, because workspaces inside containers
+    /// typically have generic paths.
+    pub fn display_name(&self, name: &str) -> String {
+        match self {
+            Remote::DevContainer { host_path } => {
+                let name = host_path
+                    .as_deref()
+                    .and_then(|path| path.trim_end_matches('/').rsplit('/').next())
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or(name);
+                format!("{} ({})", name, self)
+            }
+            Remote::Repository { repository, .. } if !repository.is_empty() => {
+                format!("{} ({})", repository, self)
+            }
+            _ => format!("{} — {}", name, self),
+        }
+    }
+
+    /// Whether this remote is only reachable from Windows.
+    pub fn is_windows_only(&self) -> bool {
+        matches!(self, Remote::Wsl { .. })
+    }
+}
+
+impl Display for Remote {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Remote::Ssh { host } => write!(f, "ssh: {}", host),
+            Remote::Wsl { distro } => write!(f, "WSL: {}", distro),
+            Remote::DevContainer { .. } => write!(f, "dev container"),
+            Remote::Tunnel { name } => write!(f, "tunnel: {}", name),
+            Remote::Codespace { name } => write!(f, "Codespace: {}", name),
+            Remote::Repository { provider, .. } => write!(f, "{}", provider),
+            Remote::Other { kind, .. } => write!(f, "{}", kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_percent_escapes() {
+        assert_eq!(percent_decode("ssh-remote%2Bfoo"), "ssh-remote+foo");
+        assert_eq!(percent_decode("my%20project"), "my project");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn parse_ssh_remote() {
+        assert_eq!(
+            Remote::from_url("vscode-remote://ssh-remote%2Bbuild.example.com/home/foo/mdcat"),
+            Some(Remote::Ssh {
+                host: "build.example.com".to_string()
+            })
+        );
+        assert_eq!(
+            Remote::from_url("vscode-remote://ssh-remote+foo/home/foo/mdcat")
+                .unwrap()
+                .to_string(),
+            "ssh: foo"
+        );
+    }
+
+    #[test]
+    fn parse_wsl_remote() {
+        let remote = Remote::from_url("vscode-remote://wsl%2BUbuntu-20.04/home/foo/mdcat").unwrap();
+        assert_eq!(
+            remote,
+            Remote::Wsl {
+                distro: "Ubuntu-20.04".to_string()
+            }
+        );
+        assert!(remote.is_windows_only());
+        assert!(
+            !Remote::from_url("vscode-remote://ssh-remote%2Bfoo/home/foo")
+                .unwrap()
+                .is_windows_only()
+        );
+    }
+
+    #[test]
+    fn parse_dev_container_remote() {
+        // "/home/foo/mdcat" in hex
+        let remote = Remote::from_url(
+            "vscode-remote://dev-container%2B2f686f6d652f666f6f2f6d64636174/workspaces/mdcat",
+        )
+        .unwrap();
+        assert_eq!(
+            remote,
+            Remote::DevContainer {
+                host_path: Some("/home/foo/mdcat".to_string())
+            }
+        );
+        assert_eq!(remote.display_name("mdcat"), "mdcat (dev container)");
+
+        // {"hostPath":"/home/foo/sbctl"} in hex
+        let remote = Remote::from_url(
+            "vscode-remote://dev-container%2B7b22686f737450617468223a222f686f6d652f666f6f2f736263746c227d/workspaces/app",
+        )
+        .unwrap();
+        assert_eq!(remote.display_name("app"), "sbctl (dev container)");
+
+        let remote =
+            Remote::from_url("vscode-remote://dev-container%2Bnothex/workspaces/app").unwrap();
+        assert_eq!(remote.display_name("app"), "app (dev container)");
+    }
+
+    #[test]
+    fn parse_remote_repository() {
+        let remote = Remote::from_url("vscode-vfs://github/swsnr/mdcat").unwrap();
+        assert_eq!(
+            remote,
+            Remote::Repository {
+                provider: "GitHub".to_string(),
+                repository: "swsnr/mdcat".to_string()
+            }
+        );
+        assert_eq!(remote.display_name("mdcat"), "swsnr/mdcat (GitHub)");
+        assert_eq!(
+            Remote::from_url("vscode-vfs://github%2B7b2276223a317d/swsnr/mdcat/src")
+                .unwrap()
+                .display_name("src"),
+            "swsnr/mdcat (GitHub)"
+        );
+    }
+
+    #[test]
+    fn parse_tunnel_remote() {
+        let remote =
+            Remote::from_url("vscode-remote://tunnel%2Bworkstation/home/foo/mdcat").unwrap();
+        assert_eq!(
+            remote,
+            Remote::Tunnel {
+                name: "workstation".to_string()
+            }
+        );
+        assert_eq!(remote.display_name("mdcat"), "mdcat — tunnel: workstation");
+    }
+
+    #[test]
+    fn parse_codespace_remote() {
+        assert_eq!(
+            Remote::from_url("vscode-remote://codespaces%2Bswsnr-mdcat-abc123/workspaces/mdcat")
+                .unwrap()
+                .display_name("mdcat"),
+            "mdcat — Codespace: swsnr-mdcat-abc123"
+        );
+    }
+
+    #[test]
+    fn remote_paths() {
+        assert_eq!(
+            remote_path("vscode-remote://ssh-remote%2Bfoo/home/foo/my%20project"),
+            Some("/home/foo/my%20project")
+        );
+        assert_eq!(remote_path("vscode-remote://ssh-remote%2Bfoo"), None);
+        assert_eq!(remote_path("file:///home/foo/mdcat"), None);
+    }
+
+    #[test]
+    fn parse_local_workspace() {
+        assert_eq!(Remote::from_url("file:///home/foo/mdcat"), None);
+    }
+}
@@ -0,0 +1,669 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Matching of search terms against workspaces.
+
+use std::sync::Arc;
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::matcher::Matcher;
+
+/// The score of each matched character.
+const SCORE_MATCH: i64 = 16;
+/// The penalty for starting a gap between matched characters.
+const PENALTY_GAP_START: i64 = 3;
+/// The penalty for each further character in a gap.
+const PENALTY_GAP_EXTENSION: i64 = 1;
+/// The bonus for a match at the start of a word.
+const BONUS_BOUNDARY: i64 = 8;
+/// The bonus for a match right after the previous match.
+const BONUS_CONSECUTIVE: i64 = 4;
+
+/// Whether the character at `index` in `text` starts a word.
+///
+/// Words start at the beginning of `text`, after any non-alphanumeric character, and at
+/// transitions from lower to upper case, as in `camelCase`.
+fn is_word_start(text: &[char], index: usize) -> bool {
+    match index.checked_sub(1).map(|previous| text[previous]) {
+        None => true,
+        Some(previous) => {
+            let current = text[index];
+            !previous.is_alphanumeric()
+                || (previous.is_lowercase() && current.is_uppercase())
+                || (!previous.is_numeric() && current.is_numeric())
+        }
+    }
+}
+
+/// Normalize `text` to Unicode normalization form C.
+///
+/// File names synced from macOS often use decomposed characters, whereas input methods
+/// typically produce composed characters.
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Strip diacritics from `c`.
+///
+/// Decompose `c`, and return the base character if all other characters of the decomposition
+/// are combining marks.  Otherwise, e.g. for Hangul syllables, return `c` as is.
+fn strip_diacritics(c: char) -> char {
+    let mut decomposed = std::iter::once(c).nfd();
+    match decomposed.next() {
+        Some(base) if decomposed.all(is_combining_mark) => base,
+        _ => c,
+    }
+}
+
+/// Split `text` into its characters, and the same characters folded for comparison.
+///
+/// Normalize `text` first, then fold characters by lowercasing them and stripping diacritics.
+/// Both vectors have the same length, so indexes into one are valid for the other.
+pub fn fold_chars(text: &str) -> (Vec<char>, Vec<char>) {
+    normalize(text)
+        .chars()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| {
+            let base = strip_diacritics(c);
+            (c, base.to_lowercase().next().unwrap_or(base))
+        })
+        .unzip()
+}
+
+/// A text prepared for matching, see [`fold_chars`].
+///
+/// Folding texts takes a while, so we fold texts of workspaces once, and not for every search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldedText {
+    /// The text as is.
+    text: String,
+    /// The characters of the normalized text.
+    original: Vec<char>,
+    /// The characters of the normalized text, folded for comparison.
+    folded: Vec<char>,
+}
+
+impl FoldedText {
+    /// Fold `text` for matching.
+    pub fn new(text: &str) -> Self {
+        let (original, folded) = fold_chars(text);
+        Self {
+            text: text.to_string(),
+            original,
+            folded,
+        }
+    }
+
+    /// The text as is.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether this text contains `pattern`, ignoring case and diacritics.
+    pub fn contains(&self, pattern: &FoldedText) -> bool {
+        pattern.folded.is_empty()
+            || self
+                .folded
+                .windows(pattern.folded.len())
+                .any(|window| window == pattern.folded.as_slice())
+    }
+}
+
+/// Fuzzy-match `pattern` against `text`, in the spirit of fzf.
+///
+/// All characters of `pattern` must appear in `text` in order, ignoring case and diacritics.
+/// Return `None` if `pattern` doesn't match, and a score otherwise; higher scores indicate
+/// better matches.
+/// Matches at word starts and consecutive matches score higher, gaps between matched
+/// characters lower the score.
+///
+/// Like fzf we first look for the first occurrence of the whole pattern as a subsequence, and
+/// then scan backwards from its end to find the shortest match.
+fn fuzzy_score(pattern: &FoldedText, text: &FoldedText) -> Option<i64> {
+    let pattern = &pattern.folded;
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let original = &text.original;
+    let lowercase = &text.folded;
+
+    // Find the end of the first match
+    let mut pattern_index = 0;
+    let mut end = None;
+    for (index, c) in lowercase.iter().enumerate() {
+        if *c == pattern[pattern_index] {
+            pattern_index += 1;
+            if pattern_index == pattern.len() {
+                end = Some(index);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Scan backwards to find the start of the shortest match ending at `end`
+    let mut pattern_index = pattern.len();
+    let mut start = end;
+    for (index, c) in lowercase[..=end].iter().enumerate().rev() {
+        if *c == pattern[pattern_index - 1] {
+            pattern_index -= 1;
+            if pattern_index == 0 {
+                start = index;
+                break;
+            }
+        }
+    }
+
+    // Score the match between start and end
+    let mut score = 0;
+    let mut pattern_index = 0;
+    let mut previous_match: Option<usize> = None;
+    for (index, c) in lowercase.iter().enumerate().take(end + 1).skip(start) {
+        if pattern_index < pattern.len() && *c == pattern[pattern_index] {
+            score += SCORE_MATCH;
+            if is_word_start(original, index) {
+                score += BONUS_BOUNDARY;
+            }
+            match previous_match {
+                Some(previous) if previous + 1 == index => score += BONUS_CONSECUTIVE,
+                Some(previous) => {
+                    let gap = (index - previous - 1) as i64;
+                    score -= PENALTY_GAP_START + (gap - 1) * PENALTY_GAP_EXTENSION;
+                }
+                None => {}
+            }
+            previous_match = Some(index);
+            pattern_index += 1;
+        }
+    }
+    Some(score)
+}
+
+/// The initials of all words in `original`, taken from its `folded` characters.
+///
+/// See [`is_word_start`] for what starts a word.
+fn initials(original: &[char], folded: &[char]) -> Vec<char> {
+    folded
+        .iter()
+        .enumerate()
+        .filter(|(index, c)| c.is_alphanumeric() && is_word_start(original, *index))
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+/// How a search term matches a name, from worst to best.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// The term fuzzily matches the name.
+    Fuzzy,
+    /// The term matches the initials of consecutive words in the name, e.g. `gsp` for
+    /// `GnomeSearchProvider`.
+    Initials,
+    /// The term appears in the name at the start of a word.
+    WordStart,
+    /// The name starts with the term.
+    Prefix,
+    /// The term is the name.
+    Exact,
+}
+
+/// The weight of a match kind in scores; larger than any fuzzy score of a typical name.
+pub const MATCH_KIND_WEIGHT: i64 = 10_000;
+
+/// Determine how `pattern` matches `text`, ignoring case and diacritics.
+///
+/// Return `None` if `pattern` doesn't match at all, and otherwise the kind of match along with
+/// its fuzzy score.
+pub fn match_term(pattern: &FoldedText, text: &FoldedText) -> Option<(MatchKind, i64)> {
+    let score = fuzzy_score(pattern, text)?;
+    let pattern = &pattern.folded;
+    let original = &text.original;
+    let lowercase = &text.folded;
+    let kind = if lowercase == pattern {
+        MatchKind::Exact
+    } else if lowercase.starts_with(pattern) {
+        MatchKind::Prefix
+    } else if !pattern.is_empty()
+        && lowercase
+            .windows(pattern.len())
+            .enumerate()
+            .any(|(index, window)| window == pattern.as_slice() && is_word_start(original, index))
+    {
+        MatchKind::WordStart
+    } else if !pattern.is_empty()
+        && initials(original, lowercase)
+            .windows(pattern.len())
+            .any(|window| window == pattern.as_slice())
+    {
+        MatchKind::Initials
+    } else {
+        MatchKind::Fuzzy
+    };
+    Some((kind, score))
+}
+
+/// Split search `terms` into individual words.
+///
+/// Gnome Shell splits the query at whitespace already, but other clients of the search provider
+/// interface may not; split terms again like Gnome Shell does, and drop empty terms.
+pub fn split_terms(terms: &[String]) -> Vec<String> {
+    terms
+        .iter()
+        .flat_map(|term| term.split_whitespace())
+        .map(normalize)
+        .collect()
+}
+
+/// A filter on workspaces, from a prefix of the search query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Only remote workspaces, from `remote:`.
+    Remote,
+    /// Only recent files, from `file:`.
+    File,
+    /// Only workspaces of the given editor, e.g. `code:` or `codium:`.
+    Editor(String),
+    /// Only local workspaces, to open in a terminal, from `term:` or `terminal:`.
+    Terminal,
+}
+
+impl Filter {
+    /// Parse the `name` of a filter prefix, without the colon.
+    ///
+    /// Return `None` if `name` can't be a filter.
+    fn parse(name: &str) -> Option<Self> {
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if is_name {
+            let filter = match name.to_lowercase().as_str() {
+                "remote" => Filter::Remote,
+                "file" => Filter::File,
+                "term" | "terminal" => Filter::Terminal,
+                editor => Filter::Editor(editor.to_string()),
+            };
+            Some(filter)
+        } else {
+            None
+        }
+    }
+}
+
+/// Split filter prefixes like `remote:` off the start of search `terms`.
+///
+/// Return the filters and the remaining terms to match.  Prefixes may stand alone, as in
+/// `remote: mdcat`, or precede the first term, as in `remote:mdcat`.
+pub fn parse_filters(terms: &[String]) -> (Vec<Filter>, Vec<String>) {
+    let mut filters = Vec::new();
+    for (index, term) in terms.iter().enumerate() {
+        let mut term = term.as_str();
+        while let Some((filter, rest)) = term
+            .split_once(':')
+            .and_then(|(name, rest)| Filter::parse(name).map(|filter| (filter, rest)))
+        {
+            filters.push(filter);
+            term = rest;
+        }
+        if !term.is_empty() {
+            let rest = std::iter::once(term.to_string())
+                .chain(terms[index + 1..].iter().cloned())
+                .collect();
+            return (filters, rest);
+        }
+    }
+    (filters, Vec::new())
+}
+
+/// Score how well all `terms` match any of the `texts` of a workspace, e.g. its name and path.
+///
+/// Return `None` if any term doesn't match any text; otherwise return the sum of the best scores
+/// of all terms, where the kind of match outweighs the fuzzy score, i.e. exact matches rank
+/// above prefix matches, which rank above matches at word starts, then matches of initials, and
+/// finally fuzzy matches.
+pub fn score_terms(texts: &[&FoldedText], terms: &[FoldedText]) -> Option<i64> {
+    terms.iter().try_fold(0, |total, term| {
+        texts
+            .iter()
+            .filter_map(|text| match_term(term, text))
+            .map(|(kind, score)| kind as i64 * MATCH_KIND_WEIGHT + score)
+            .max()
+            .map(|score| total + score)
+    })
+}
+
+/// Workspaces at or beyond this position in the list of recent workspaces are stale.
+const STALE_RECENCY: usize = 50;
+
+/// The maximum number of stale workspaces to return for a search.
+const MAX_STALE_RESULTS: usize = 5;
+
+/// The weight of the frecency of a candidate in scores.
+///
+/// Frecency is at most 1000, so it boosts often launched candidates, but does not outweigh a
+/// better kind of match.
+const FRECENCY_WEIGHT: i64 = 5;
+
+/// A candidate for search results.
+#[derive(Debug)]
+pub struct Candidate<'a> {
+    /// The ID of the candidate.
+    pub id: &'a Arc<str>,
+    /// Texts to match search terms against, e.g. the name of the workspace.
+    pub texts: Vec<&'a FoldedText>,
+    /// The position of the workspace in the list of recent workspaces; 0 is most recent.
+    pub recency: usize,
+    /// How frequently and recently the candidate was launched from search results.
+    pub frecency: i64,
+}
+
+/// Rank `candidates` by how well their texts match with `matcher`.
+///
+/// Return the IDs of all matching candidates, best match first, and more recent candidates
+/// first among equally good matches.  Boost candidates by their frecency, to rank workspaces
+/// often launched from search results first.  Return at most a few stale candidates which were
+/// never launched, to avoid flooding results with workspaces not opened for a long time.
+pub fn rank_matches<'a, I>(matcher: &dyn Matcher, candidates: I) -> Vec<Arc<str>>
+where
+    I: IntoIterator<Item = Candidate<'a>>,
+{
+    let mut scored: Vec<(i64, Candidate)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            matcher
+                .score(&candidate.texts)
+                .map(|score| (score + candidate.frecency * FRECENCY_WEIGHT, candidate))
+        })
+        .collect();
+    scored
+        .sort_by(|(score_a, a), (score_b, b)| score_b.cmp(score_a).then(a.recency.cmp(&b.recency)));
+    let mut stale = 0;
+    scored
+        .into_iter()
+        .filter(|(_, candidate)| {
+            if STALE_RECENCY <= candidate.recency && candidate.frecency == 0 {
+                stale += 1;
+                stale <= MAX_STALE_RESULTS
+            } else {
+                true
+            }
+        })
+        .map(|(_, candidate)| candidate.id.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatcherKind;
+
+    fn as_strs(ids: &[Arc<str>]) -> Vec<&str> {
+        ids.iter().map(|id| &**id).collect()
+    }
+
+    fn fuzzy(terms: &[&str]) -> Box<dyn Matcher> {
+        let terms: Vec<String> = terms.iter().map(|term| term.to_string()).collect();
+        MatcherKind::Fuzzy.matcher(&terms)
+    }
+
+    fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+        super::fuzzy_score(&FoldedText::new(pattern), &FoldedText::new(text))
+    }
+
+    fn match_term(pattern: &str, text: &str) -> Option<(MatchKind, i64)> {
+        super::match_term(&FoldedText::new(pattern), &FoldedText::new(text))
+    }
+
+    fn score_terms(texts: &[&str], terms: &[String]) -> Option<i64> {
+        let texts: Vec<FoldedText> = texts.iter().map(|text| FoldedText::new(text)).collect();
+        let terms: Vec<FoldedText> = terms.iter().map(|term| FoldedText::new(term)).collect();
+        super::score_terms(&texts.iter().collect::<Vec<_>>(), &terms)
+    }
+
+    #[test]
+    fn fuzzy_match_subsequence() {
+        assert!(fuzzy_score("gnsp", "gnome-search-provider").is_some());
+        assert!(fuzzy_score("GSP", "gnome-search-provider").is_some());
+        assert!(fuzzy_score("mdc", "mdcat").is_some());
+        assert!(fuzzy_score("", "mdcat").is_some());
+        assert!(fuzzy_score("mdx", "mdcat").is_none());
+        assert!(fuzzy_score("tacdm", "mdcat").is_none());
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_word_starts_and_consecutive_matches() {
+        let consecutive = fuzzy_score("search", "gnome-search-provider").unwrap();
+        let scattered = fuzzy_score("search", "some-extra-arch").unwrap();
+        assert!(consecutive > scattered);
+
+        let lower = fuzzy_score("gsp", "gnomesearchprovider").unwrap();
+        let boundaries = fuzzy_score("gsp", "gnome-search-provider").unwrap();
+        let camel = fuzzy_score("gsp", "GnomeSearchProvider").unwrap();
+        assert!(boundaries > lower);
+        assert!(camel > lower);
+    }
+
+    #[test]
+    fn fuzzy_score_finds_shortest_match() {
+        // Scanning backwards skips the "a" at the start
+        assert_eq!(fuzzy_score("ab", "a-xab"), fuzzy_score("ab", "-xab"));
+    }
+
+    #[test]
+    fn fold_case_and_diacritics() {
+        assert_eq!(fold_chars("Résumé").1, vec!['r', 'e', 's', 'u', 'm', 'e']);
+        // Decomposed e with combining acute accent
+        assert_eq!(
+            fold_chars("Re\u{301}sume\u{301}").1,
+            vec!['r', 'e', 's', 'u', 'm', 'e']
+        );
+        assert_eq!(
+            match_term("resume", "Résumé-Site").unwrap().0,
+            MatchKind::Prefix
+        );
+        assert_eq!(match_term("RÉSUMÉ", "resume").unwrap().0, MatchKind::Exact);
+    }
+
+    #[test]
+    fn match_composed_and_decomposed() {
+        // Composed and decomposed Hangul syllables
+        let composed = "\u{d55c}\u{ae00}";
+        let decomposed = "\u{1112}\u{1161}\u{11ab}\u{1100}\u{1173}\u{11af}";
+        assert_eq!(normalize(decomposed), composed);
+        assert_eq!(
+            match_term(composed, decomposed).unwrap().0,
+            MatchKind::Exact
+        );
+        assert_eq!(
+            match_term(decomposed, composed).unwrap().0,
+            MatchKind::Exact
+        );
+        assert_eq!(
+            split_terms(&[decomposed.to_string()]),
+            vec![composed.to_string()]
+        );
+    }
+
+    #[test]
+    fn match_kinds() {
+        assert_eq!(match_term("mdcat", "MDcat").unwrap().0, MatchKind::Exact);
+        assert_eq!(match_term("md", "mdcat").unwrap().0, MatchKind::Prefix);
+        assert_eq!(
+            match_term("search", "gnome-search-provider").unwrap().0,
+            MatchKind::WordStart
+        );
+        assert_eq!(match_term("cat", "mdcat").unwrap().0, MatchKind::Fuzzy);
+        assert_eq!(
+            match_term("gnsp", "gnome-search-provider").unwrap().0,
+            MatchKind::Fuzzy
+        );
+        assert_eq!(match_term("foo", "mdcat"), None);
+    }
+
+    #[test]
+    fn match_initials() {
+        assert_eq!(
+            match_term("GSP", "GnomeSearchProvider").unwrap().0,
+            MatchKind::Initials
+        );
+        assert_eq!(
+            match_term("gsp", "gnome-search-provider").unwrap().0,
+            MatchKind::Initials
+        );
+        assert_eq!(
+            match_term("sp", "gnome_search_provider_vscode").unwrap().0,
+            MatchKind::Initials
+        );
+        assert_eq!(
+            match_term("gp", "gnome-search-provider").unwrap().0,
+            MatchKind::Fuzzy
+        );
+    }
+
+    #[test]
+    fn split_terms_at_whitespace() {
+        assert_eq!(
+            split_terms(&[
+                "search provider".to_string(),
+                " rust ".to_string(),
+                "".to_string()
+            ]),
+            vec!["search", "provider", "rust"]
+        );
+    }
+
+    #[test]
+    fn score_requires_all_terms() {
+        let terms = split_terms(&["search provider rust".to_string()]);
+        assert!(score_terms(&["gnome-search-provider-rust"], &terms).is_some());
+        assert!(score_terms(&["gnome-search-provider"], &terms).is_none());
+    }
+
+    #[test]
+    fn score_all_terms() {
+        let terms = vec!["MD".to_string(), "cat".to_string()];
+        assert!(score_terms(&["mdcat"], &terms).is_some());
+        assert!(score_terms(&["sbctl"], &terms).is_none());
+    }
+
+    #[test]
+    fn score_best_text() {
+        let texts = ["billing", "/home/foo/work/acme/billing"];
+        let terms = vec!["acme".to_string(), "billing".to_string()];
+        assert_eq!(
+            score_terms(&texts, &terms),
+            Some(
+                score_terms(&["/home/foo/work/acme/billing"], &terms[..1]).unwrap()
+                    + score_terms(&["billing"], &terms[1..]).unwrap()
+            )
+        );
+        assert_eq!(score_terms(&texts[..1], &terms), None);
+    }
+
+    #[test]
+    fn parse_filter_prefixes() {
+        let terms = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_filters(&terms(&["remote:", "mdcat"])),
+            (vec![Filter::Remote], terms(&["mdcat"]))
+        );
+        assert_eq!(
+            parse_filters(&terms(&["Codium:file:mdcat", "readme"])),
+            (
+                vec![Filter::Editor("codium".to_string()), Filter::File],
+                terms(&["mdcat", "readme"])
+            )
+        );
+        assert_eq!(
+            parse_filters(&terms(&["mdcat", "remote:"])),
+            (vec![], terms(&["mdcat", "remote:"]))
+        );
+        assert_eq!(
+            parse_filters(&terms(&["c++:foo"])),
+            (vec![], terms(&["c++:foo"]))
+        );
+        assert_eq!(
+            parse_filters(&terms(&["file:"])),
+            (vec![Filter::File], vec![])
+        );
+        assert_eq!(
+            parse_filters(&terms(&["term:", "mdcat"])),
+            (vec![Filter::Terminal], terms(&["mdcat"]))
+        );
+    }
+
+    #[test]
+    fn rank_by_recency_and_cap_stale() {
+        let ids: Vec<Arc<str>> = (0..100).map(|i| i.to_string().into()).collect();
+        let mdcat = FoldedText::new("mdcat");
+        let candidates = ids.iter().rev().map(|id| Candidate {
+            id,
+            texts: vec![&mdcat],
+            recency: id.parse().unwrap(),
+            frecency: 0,
+        });
+        let expected: Vec<Arc<str>> = (0..(STALE_RECENCY + MAX_STALE_RESULTS))
+            .map(|i| i.to_string().into())
+            .collect();
+        assert_eq!(rank_matches(fuzzy(&["md"]).as_ref(), candidates), expected);
+    }
+
+    #[test]
+    fn rank_by_match_kind_then_recency() {
+        let ids: Vec<Arc<str>> = vec!["1", "2", "3", "4", "5"]
+            .into_iter()
+            .map(Arc::from)
+            .collect();
+        let names: Vec<FoldedText> = ["my-app", "app-server", "apple", "app", "other-app"]
+            .iter()
+            .map(|name| FoldedText::new(name))
+            .collect();
+        let candidates = ids
+            .iter()
+            .zip(names.iter())
+            .enumerate()
+            .map(|(recency, (id, name))| Candidate {
+                id,
+                texts: vec![name],
+                recency,
+                frecency: 0,
+            });
+        assert_eq!(
+            as_strs(&rank_matches(fuzzy(&["app"]).as_ref(), candidates)),
+            vec!["4", "2", "3", "1", "5"]
+        );
+    }
+
+    #[test]
+    fn rank_by_frecency() {
+        let ids: Vec<Arc<str>> = vec!["1", "2", "3", "4"]
+            .into_iter()
+            .map(Arc::from)
+            .collect();
+        let names: Vec<FoldedText> = ["app-server", "my-app", "app", "old-app"]
+            .iter()
+            .map(|name| FoldedText::new(name))
+            .collect();
+        let frecencies = [0, 0, 0, 100];
+        let candidates =
+            ids.iter()
+                .zip(names.iter())
+                .zip(frecencies.iter())
+                .map(|((id, name), frecency)| Candidate {
+                    id,
+                    texts: vec![name],
+                    recency: if *frecency == 0 { 0 } else { STALE_RECENCY },
+                    frecency: *frecency,
+                });
+        // Frecency boosts within a kind of match, and keeps launched stale workspaces
+        assert_eq!(
+            as_strs(&rank_matches(fuzzy(&["app"]).as_ref(), candidates)),
+            vec!["3", "1", "4", "2"]
+        );
+    }
+}
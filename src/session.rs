@@ -0,0 +1,169 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The environment of the graphical session.
+//!
+//! DBus or systemd may start this service before the graphical session is up, or keep it running
+//! across sessions, so our own environment may lack or have stale display variables.  The systemd
+//! user manager always has the current environment of the session, because Gnome imports it
+//! there on login.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use log::{debug, trace};
+use zvariant::{OwnedValue, Value};
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::glib;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+/// Variables which describe the graphical session.
+const SESSION_VARIABLES: [&str; 6] = [
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+    "XDG_SESSION_TYPE",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_SESSION_DESKTOP",
+];
+
+/// Extract the variables of the graphical session from environment `assignments`.
+///
+/// Each assignment has the form `NAME=VALUE`.
+fn session_variables(assignments: &[String]) -> Vec<(&str, &str)> {
+    assignments
+        .iter()
+        .filter_map(|assignment| assignment.split_once('='))
+        .filter(|(name, _)| SESSION_VARIABLES.contains(name))
+        .collect()
+}
+
+/// Extract environment assignments from the value of the `Environment` property of the systemd
+/// user manager.
+///
+/// `Properties.Get` wraps the array of assignments in a variant.
+fn environment_assignments(value: &Value<'_>) -> Vec<String> {
+    match value {
+        Value::Value(value) => environment_assignments(value),
+        Value::Array(array) => array
+            .get()
+            .iter()
+            .filter_map(|value| match value {
+                Value::Str(assignment) => Some(assignment.as_str().to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Keeps the environment of launched apps in line with the graphical session.
+///
+/// Clones share the connection, the launch context, and pending requests.
+#[derive(Clone)]
+pub struct SessionEnvironment {
+    /// The connection to ask the systemd user manager on.
+    connection: zbus::azync::Connection,
+    /// The context to launch apps in.
+    context: gio::AppLaunchContext,
+    /// Whether we wait for the systemd user manager.
+    pending: Rc<Cell<bool>>,
+}
+
+impl SessionEnvironment {
+    /// Pass the environment of the graphical session to apps launched with `context`, asking the
+    /// systemd user manager on `connection`.
+    pub fn new(connection: zbus::azync::Connection, context: gio::AppLaunchContext) -> Self {
+        Self {
+            connection,
+            context,
+            pending: Rc::default(),
+        }
+    }
+
+    /// Get the environment of the systemd user manager.
+    async fn manager_environment(&self) -> zbus::Result<Vec<String>> {
+        let reply = self
+            .connection
+            .call_method(
+                Some("org.freedesktop.systemd1"),
+                "/org/freedesktop/systemd1",
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &("org.freedesktop.systemd1.Manager", "Environment"),
+            )
+            .await?;
+        let environment: OwnedValue = reply.body()?;
+        Ok(environment_assignments(&environment))
+    }
+
+    /// Pass the variables of the current graphical session to launched apps.
+    ///
+    /// Ask the systemd user manager on the main loop, and don't wait for its reply, so that
+    /// requests never block on the manager; apps launched meanwhile get the variables of the
+    /// last reply.  Keep the environment as it is if the manager is unavailable.
+    pub fn refresh(&self) {
+        if self.pending.replace(true) {
+            return;
+        }
+        let session = self.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            match session.manager_environment().await {
+                Ok(environment) => {
+                    for (name, value) in session_variables(&environment) {
+                        trace!("Setting {}={} for launched apps", name, value);
+                        session.context.setenv(name, value);
+                    }
+                }
+                Err(error) => debug!("Failed to get environment of session: {}", error),
+            }
+            session.pending.set(false);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_session_variables() {
+        let environment: Vec<String> = [
+            "HOME=/home/foo",
+            "WAYLAND_DISPLAY=wayland-0",
+            "DISPLAY=:0",
+            "XDG_CURRENT_DESKTOP=GNOME",
+            "INVALID",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(
+            session_variables(&environment),
+            vec![
+                ("WAYLAND_DISPLAY", "wayland-0"),
+                ("DISPLAY", ":0"),
+                ("XDG_CURRENT_DESKTOP", "GNOME")
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_environment_assignments() {
+        let assignments = vec!["HOME=/home/foo", "DISPLAY=:0"];
+        let expected = vec!["HOME=/home/foo".to_string(), "DISPLAY=:0".to_string()];
+        assert_eq!(
+            environment_assignments(&Value::from(assignments.clone())),
+            expected
+        );
+        assert_eq!(
+            environment_assignments(&Value::Value(Box::new(Value::from(assignments)))),
+            expected
+        );
+        assert!(environment_assignments(&Value::from("DISPLAY=:0")).is_empty());
+    }
+}
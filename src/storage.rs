@@ -0,0 +1,878 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Storage of recent workspaces in VSCode variants.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use log::{debug, trace, warn};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Map;
+
+/// Known keys of the list of recently opened paths, in order of preference.
+///
+/// VSCode used `openedPathsList` in `storage.json`, and uses `history.recentlyOpenedPathsList`
+/// in `state.vscdb` from code 1.64.
+const OPENED_PATHS_LIST_KEYS: [&str; 2] = ["history.recentlyOpenedPathsList", "openedPathsList"];
+
+/// The key of the list of user data profiles.
+const USER_DATA_PROFILES_KEY: &str = "userDataProfiles";
+
+/// A multi-root workspace in the list of recently opened paths.
+#[derive(Debug, Deserialize)]
+struct StorageWorkspace {
+    /// The URI of the `.code-workspace` file.
+    #[serde(rename = "configPath")]
+    config_path: String,
+}
+
+/// An entry in the list of recently opened paths, from code 1.55.
+#[derive(Debug, Deserialize)]
+struct StorageOpenedPathsListEntry {
+    #[serde(rename = "folderUri")]
+    folder_uri: Option<String>,
+    #[serde(rename = "fileUri")]
+    file_uri: Option<String>,
+    workspace: Option<StorageWorkspace>,
+    /// A human readable label for this entry, e.g. for remote workspaces.
+    label: Option<String>,
+}
+
+/// Deserialize a list element by element, and skip elements which fail to deserialize.
+///
+/// VSCode changes its storage formats every now and then; we'd rather skip a single entry we
+/// don't understand than fail to read all recent workspaces.
+fn deserialize_tolerant_list<'de, D, T>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let values: Option<Vec<serde_json::Value>> = Option::deserialize(deserializer)?;
+    Ok(values.map(|values| {
+        values
+            .into_iter()
+            .filter_map(|value| match T::deserialize(&value) {
+                Ok(element) => Some(element),
+                Err(error) => {
+                    warn!("Skipping malformed entry {}: {}", value, error);
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+/// The list of recently opened paths.
+#[derive(Debug, Deserialize)]
+struct StorageOpenedPathsList {
+    /// Up to code 1.54
+    #[serde(default, deserialize_with = "deserialize_tolerant_list")]
+    workspaces3: Option<Vec<String>>,
+    /// From code 1.55
+    #[serde(default, deserialize_with = "deserialize_tolerant_list")]
+    entries: Option<Vec<StorageOpenedPathsListEntry>>,
+}
+
+/// The file extension of multi-root workspace files.
+pub const CODE_WORKSPACE_EXTENSION: &str = ".code-workspace";
+
+/// What a recent workspace refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecentKind {
+    /// A folder.
+    Folder,
+    /// A multi-root workspace, i.e. a `.code-workspace` file.
+    Workspace,
+    /// A single file.
+    File,
+}
+
+/// A recently opened workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentWorkspace {
+    /// The workspace URL.
+    pub url: String,
+    /// The label VSCode shows for this workspace, if any.
+    pub label: Option<String>,
+    /// What this workspace refers to.
+    pub kind: RecentKind,
+    /// The name of the user data profile this workspace was found in, if any.
+    pub profile: Option<String>,
+    /// When VSCode last opened this workspace, if known.
+    pub last_opened: Option<SystemTime>,
+}
+
+impl RecentWorkspace {
+    /// A recent folder or multi-root workspace with the given `url` and no label.
+    pub fn new(url: String) -> Self {
+        let kind = if url.ends_with(CODE_WORKSPACE_EXTENSION) {
+            RecentKind::Workspace
+        } else {
+            RecentKind::Folder
+        };
+        Self {
+            url,
+            label: None,
+            kind,
+            profile: None,
+            last_opened: None,
+        }
+    }
+
+    /// A recent file with the given `url` and no label.
+    pub fn file(url: String) -> Self {
+        Self {
+            kind: RecentKind::File,
+            ..Self::new(url)
+        }
+    }
+}
+
+/// The contents of a `.code-workspace` file, as far as we need them.
+#[derive(Debug, Deserialize)]
+struct CodeWorkspaceFile {
+    /// The name of the workspace, if any.
+    name: Option<String>,
+}
+
+/// Read the name stored in the `contents` of a `.code-workspace` file, if any.
+///
+/// Return `None` if the file has no name, or if we can't parse it, e.g. because it has
+/// comments.
+fn code_workspace_name(contents: &str) -> Option<String> {
+    serde_json::from_str::<CodeWorkspaceFile>(contents)
+        .ok()
+        .and_then(|file| file.name)
+        .filter(|name| !name.trim().is_empty())
+}
+
+/// A cache of names stored in `.code-workspace` files.
+///
+/// Remember the name of each file along with its modification time, to only read the file
+/// again after it changed.
+#[derive(Debug, Default)]
+pub struct CodeWorkspaceNames {
+    /// The modification time and the name, by path of the workspace file.
+    names: HashMap<PathBuf, (SystemTime, Option<String>)>,
+}
+
+impl CodeWorkspaceNames {
+    /// The name stored in the `.code-workspace` file at `path`, if any.
+    pub fn name(&mut self, path: &Path) -> Option<String> {
+        let mtime = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if let Some((cached_mtime, name)) = self.names.get(path) {
+            if *cached_mtime == mtime {
+                return name.clone();
+            }
+        }
+        trace!("Reading workspace name from {}", path.display());
+        let name = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| code_workspace_name(&contents));
+        self.names.insert(path.to_path_buf(), (mtime, name.clone()));
+        name
+    }
+}
+
+/// A user data profile of a VSCode variant.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct UserDataProfile {
+    /// The location of the profile, i.e. the name of its directory in `User/profiles`.
+    pub location: String,
+    /// The name of the profile.
+    pub name: String,
+}
+
+/// The storage of a VSCode variant.
+#[derive(Debug)]
+pub struct Storage {
+    opened_paths_list: Option<StorageOpenedPathsList>,
+    /// User data profiles, from code 1.75.
+    pub user_data_profiles: Vec<UserDataProfile>,
+}
+
+impl Storage {
+    /// Create a storage from the given key-value `items`.
+    ///
+    /// VSCode renamed the key of the list of recently opened paths a few times.  Try all known
+    /// keys in order, and use the first one which exists.
+    fn from_items(mut items: Map<String, serde_json::Value>) -> Result<Self> {
+        let mut opened_paths_list = None;
+        for key in OPENED_PATHS_LIST_KEYS.iter() {
+            if let Some(value) = items.remove(*key) {
+                debug!("Found recently opened paths at key {}", key);
+                opened_paths_list = Some(serde_json::from_value(value).with_context(|| {
+                    format!("Failed to parse recently opened paths at {}", key)
+                })?);
+                break;
+            }
+        }
+        if opened_paths_list.is_none() {
+            debug!(
+                "Found no recently opened paths at any known key, have keys {:?}",
+                items.keys().collect::<Vec<_>>()
+            );
+        }
+        let user_data_profiles = items
+            .remove(USER_DATA_PROFILES_KEY)
+            .map(serde_json::from_value)
+            .transpose()
+            .context("Failed to parse user data profiles")?
+            .unwrap_or_default();
+        Ok(Self {
+            opened_paths_list,
+            user_data_profiles,
+        })
+    }
+
+    /// Read a VSCode storage.json from the given `reader`.
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        Self::from_items(serde_json::from_reader(reader)?)
+    }
+
+    /// Read the `storage.json` file in the given `config_dir`.
+    pub fn from_dir<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        let path = config_dir.as_ref().join("storage.json");
+        trace!("Reading storage from {}", path.display());
+        Self::read(
+            File::open(&path)
+                .with_context(|| format!("Failed to open {} for reading", path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse storage from {}", path.display()))
+    }
+
+    /// Read the `state.vscdb` SQLite database in the given `config_dir`.
+    ///
+    /// From code 1.64 VSCode keeps the list of recently opened paths in this database instead of
+    /// `storage.json`.
+    pub fn from_state_db<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        Self::from_state_db_file(
+            config_dir
+                .as_ref()
+                .join("User")
+                .join("globalStorage")
+                .join("state.vscdb"),
+        )
+    }
+
+    /// Read the `state.vscdb` SQLite database at the given `path`.
+    pub fn from_state_db_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        trace!("Reading storage from {}", path.display());
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("Failed to open {} for reading", path.display()))?;
+        let mut items = Map::new();
+        for key in OPENED_PATHS_LIST_KEYS
+            .iter()
+            .chain(std::iter::once(&USER_DATA_PROFILES_KEY))
+        {
+            // VSCode declares the value column as BLOB, but actually stores text
+            let value: Option<Value> = connection
+                .query_row(
+                    "SELECT value FROM ItemTable WHERE key = ?",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()
+                .with_context(|| format!("Failed to query storage from {}", path.display()))?;
+            let value = match value {
+                Some(Value::Text(text)) => text.into_bytes(),
+                Some(Value::Blob(blob)) => blob,
+                _ => continue,
+            };
+            let value = serde_json::from_slice(&value)
+                .with_context(|| format!("Failed to parse {} from {}", key, path.display()))?;
+            items.insert(key.to_string(), value);
+        }
+        Self::from_items(items)
+            .with_context(|| format!("Failed to parse storage from {}", path.display()))
+    }
+
+    /// Read the `storage.json` file of the VSCode variant with the given `config_dir`.
+    ///
+    /// Read `storage.json` from `config_dir` if it exists, and otherwise from
+    /// `User/globalStorage`, where VSCode keeps it from code 1.64.
+    pub fn from_storage_json<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        let config_dir = config_dir.as_ref();
+        if config_dir.join("storage.json").is_file() {
+            Self::from_dir(config_dir)
+        } else {
+            Self::from_dir(config_dir.join("User").join("globalStorage"))
+        }
+    }
+
+    /// Move this storage into recent workspaces.
+    pub fn into_workspaces(self) -> Vec<RecentWorkspace> {
+        trace!("Extracting workspaces from {:?}", self);
+        if let Some(paths) = self.opened_paths_list {
+            let entries = paths.entries.unwrap_or_default();
+            let workspaces3 = paths.workspaces3.unwrap_or_default();
+            entries
+                .into_iter()
+                .filter_map(|entry| {
+                    let workspace = match (entry.folder_uri, entry.workspace, entry.file_uri) {
+                        (Some(url), _, _) => RecentWorkspace::new(url),
+                        (None, Some(workspace), _) => RecentWorkspace {
+                            kind: RecentKind::Workspace,
+                            ..RecentWorkspace::new(workspace.config_path)
+                        },
+                        (None, None, Some(url)) => RecentWorkspace::file(url),
+                        (None, None, None) => return None,
+                    };
+                    Some(RecentWorkspace {
+                        label: entry.label,
+                        ..workspace
+                    })
+                })
+                .chain(workspaces3.into_iter().map(RecentWorkspace::new))
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// The `workspace.json` file of a workspace storage.
+#[derive(Debug, Deserialize)]
+struct WorkspaceStorageWorkspace {
+    /// The URL of a folder.
+    folder: Option<String>,
+    /// The URL of a `.code-workspace` file.
+    workspace: Option<String>,
+}
+
+impl WorkspaceStorageWorkspace {
+    /// Read `workspace.json` from the given `reader`.
+    fn read<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(Into::into)
+    }
+
+    /// Read the `workspace.json` file in the given `workspace_storage_dir`.
+    fn from_dir<P: AsRef<Path>>(workspace_storage_dir: P) -> Result<Self> {
+        let path = workspace_storage_dir.as_ref().join("workspace.json");
+        Self::read(
+            File::open(&path)
+                .with_context(|| format!("Failed to open {} for reading", path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse workspace from {}", path.display()))
+    }
+}
+
+/// Read workspaces from the workspace storage of the VSCode variant with the given
+/// `config_dir`.
+///
+/// VSCode keeps a directory with state for every workspace it ever opened in
+/// `User/workspaceStorage`, with a `workspace.json` file which refers to the workspace.
+/// Return these workspaces, most recently modified first, and use the modification time of
+/// their state directory as the time VSCode last opened them.
+pub fn workspace_storage<P: AsRef<Path>>(config_dir: P) -> Result<Vec<RecentWorkspace>> {
+    let workspace_storage_dir = config_dir.as_ref().join("User").join("workspaceStorage");
+    trace!(
+        "Reading workspace storage from {}",
+        workspace_storage_dir.display()
+    );
+    let mut workspaces = Vec::new();
+    for entry in std::fs::read_dir(&workspace_storage_dir).with_context(|| {
+        format!(
+            "Failed to read workspace storage from {}",
+            workspace_storage_dir.display()
+        )
+    })? {
+        let entry = entry?;
+        if !entry.path().join("workspace.json").is_file() {
+            continue;
+        }
+        match WorkspaceStorageWorkspace::from_dir(entry.path()) {
+            Ok(workspace) => {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok();
+                if let Some(url) = workspace.folder.or(workspace.workspace) {
+                    workspaces.push((modified, url));
+                }
+            }
+            Err(error) => debug!("Skipping workspace storage: {:#}", error),
+        }
+    }
+    workspaces.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(workspaces
+        .into_iter()
+        .map(|(modified, url)| RecentWorkspace {
+            last_opened: modified,
+            ..RecentWorkspace::new(url)
+        })
+        .collect())
+}
+
+/// A multi-root workspace in `Backups/workspaces.json`.
+#[derive(Debug, Deserialize)]
+struct BackupRootWorkspace {
+    /// The URI of the `.code-workspace` file.
+    #[serde(rename = "configURIPath")]
+    config_uri_path: String,
+}
+
+/// A folder in `Backups/workspaces.json`.
+#[derive(Debug, Deserialize)]
+struct BackupFolder {
+    /// The URI of the folder.
+    #[serde(rename = "folderUri")]
+    folder_uri: String,
+}
+
+/// Workspaces with backups of a VSCode variant.
+///
+/// VSCode keeps track of all workspaces with unsaved changes in `Backups/workspaces.json`,
+/// including workspaces of windows which crashed.
+#[derive(Debug, Deserialize)]
+pub struct BackupWorkspaces {
+    /// Multi-root workspaces.
+    #[serde(rename = "rootURIWorkspaces", default)]
+    root_uri_workspaces: Vec<BackupRootWorkspace>,
+    /// Folders, up to code 1.54.
+    #[serde(rename = "folderURIWorkspaces", default)]
+    folder_uri_workspaces: Vec<String>,
+    /// Folders, from code 1.55.
+    #[serde(rename = "folderWorkspaceInfos", default)]
+    folder_workspace_infos: Vec<BackupFolder>,
+}
+
+impl BackupWorkspaces {
+    /// Read `workspaces.json` from the given `reader`.
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(Into::into)
+    }
+
+    /// Read the `Backups/workspaces.json` file of the VSCode variant with the given `config_dir`.
+    pub fn from_dir<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        let path = config_dir.as_ref().join("Backups").join("workspaces.json");
+        trace!("Reading backup workspaces from {}", path.display());
+        Self::read(
+            File::open(&path)
+                .with_context(|| format!("Failed to open {} for reading", path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse backup workspaces from {}", path.display()))
+    }
+
+    /// Move these backups into recent workspaces.
+    pub fn into_workspaces(self) -> Vec<RecentWorkspace> {
+        let folders = self
+            .folder_workspace_infos
+            .into_iter()
+            .map(|folder| folder.folder_uri)
+            .chain(self.folder_uri_workspaces);
+        let workspaces = self
+            .root_uri_workspaces
+            .into_iter()
+            .map(|workspace| workspace.config_uri_path);
+        folders
+            .chain(workspaces)
+            .map(RecentWorkspace::new)
+            .collect()
+    }
+}
+
+/// Recent workspaces of Theia.
+#[derive(Debug, Deserialize)]
+pub struct TheiaRecentWorkspaces {
+    /// Recent workspace URLs.
+    #[serde(rename = "recentRoots", default)]
+    pub recent_roots: Vec<String>,
+}
+
+impl TheiaRecentWorkspaces {
+    /// Read Theia's `recentworkspace.json` from the given `reader`.
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(Into::into)
+    }
+
+    /// Read the `recentworkspace.json` file in the given `config_dir`.
+    pub fn from_dir<P: AsRef<Path>>(config_dir: P) -> Result<Self> {
+        let path = config_dir.as_ref().join("recentworkspace.json");
+        trace!("Reading recent workspaces from {}", path.display());
+        Self::read(
+            File::open(&path)
+                .with_context(|| format!("Failed to open {} for reading", path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse recent workspaces from {}", path.display()))
+    }
+}
+
+/// The modification time and size of a file or directory, if it exists.
+pub type FileStamp = Option<(SystemTime, u64)>;
+
+/// Files and directories in `config_dir` which VSCode keeps recent workspaces in.
+///
+/// Include the directories of workspace storage and of profiles, whose modification time changes
+/// when VSCode adds or removes workspaces or profiles.
+pub fn storage_paths(config_dir: &Path) -> Vec<PathBuf> {
+    let user_dir = config_dir.join("User");
+    let profiles_dir = user_dir.join("profiles");
+    let mut storage_dirs = vec![user_dir.join("globalStorage")];
+    if let Ok(entries) = std::fs::read_dir(&profiles_dir) {
+        storage_dirs.extend(
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().join("globalStorage")),
+        );
+    }
+    let mut paths = vec![
+        config_dir.join("storage.json"),
+        config_dir.join("Backups").join("workspaces.json"),
+        user_dir.join("workspaceStorage"),
+        profiles_dir,
+    ];
+    for dir in storage_dirs {
+        paths.push(dir.join("storage.json"));
+        paths.push(dir.join("state.vscdb"));
+        paths.push(dir.join("state.vscdb-wal"));
+    }
+    paths.sort();
+    paths
+}
+
+/// Stamp all files and directories which VSCode keeps recent workspaces in in `config_dir`.
+///
+/// The stamps change whenever VSCode changes recent workspaces.
+pub fn storage_stamps(config_dir: &Path) -> Vec<(PathBuf, FileStamp)> {
+    storage_paths(config_dir)
+        .into_iter()
+        .map(|path| {
+            let stamp = std::fs::metadata(&path)
+                .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                .ok();
+            (path, stamp)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// The URLs of the given `workspaces`.
+    fn urls(workspaces: Vec<RecentWorkspace>) -> Vec<String> {
+        workspaces.into_iter().map(|w| w.url).collect()
+    }
+
+    #[test]
+    fn read_code_workspace_name() {
+        assert_eq!(
+            code_workspace_name(r#"{"name": "Gnome", "folders": []}"#).as_deref(),
+            Some("Gnome")
+        );
+        assert_eq!(code_workspace_name(r#"{"folders": []}"#), None);
+        assert_eq!(code_workspace_name("// comment\n{}"), None);
+    }
+
+    /// Create an empty temporary directory for the test with the given `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "{}-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            name,
+            std::process::id()
+        ));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn storage_stamps_change_with_storage() {
+        let config_dir = temp_dir("storage-stamps");
+        std::fs::create_dir_all(config_dir.join("User").join("profiles").join("-1a2b")).unwrap();
+        let empty = storage_stamps(&config_dir);
+        assert!(empty.iter().any(|(path, _)| path
+            == &config_dir
+                .join("User")
+                .join("profiles")
+                .join("-1a2b")
+                .join("globalStorage")
+                .join("state.vscdb")));
+        assert_eq!(storage_stamps(&config_dir), empty);
+        std::fs::write(config_dir.join("storage.json"), "{}").unwrap();
+        assert_ne!(storage_stamps(&config_dir), empty);
+        std::fs::remove_dir_all(&config_dir).unwrap();
+    }
+
+    #[test]
+    fn read_workspace_storage() {
+        let config_dir = temp_dir("workspace-storage");
+        let workspace_storage_dir = config_dir.join("User").join("workspaceStorage");
+        for (hash, contents) in &[
+            ("1a2b", r#"{"folder": "file:///home/foo/mdcat"}"#),
+            (
+                "3c4d",
+                r#"{"workspace": "file:///home/foo/gnome.code-workspace"}"#,
+            ),
+            ("5e6f", "{}"),
+        ] {
+            let dir = workspace_storage_dir.join(hash);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("workspace.json"), contents).unwrap();
+        }
+        std::fs::create_dir_all(workspace_storage_dir.join("ext-dev")).unwrap();
+
+        let mut urls: Vec<String> = workspace_storage(&config_dir)
+            .unwrap()
+            .into_iter()
+            .map(|workspace| workspace.url)
+            .collect();
+        urls.sort();
+        assert_eq!(
+            urls,
+            vec![
+                "file:///home/foo/gnome.code-workspace",
+                "file:///home/foo/mdcat"
+            ]
+        );
+        std::fs::remove_dir_all(&config_dir).unwrap();
+    }
+
+    #[test]
+    fn read_recent_workspaces_state_db() {
+        let config_dir = temp_dir("state-db");
+        let storage_dir = config_dir.join("User").join("globalStorage");
+        std::fs::create_dir_all(&storage_dir).unwrap();
+        let connection = Connection::open(storage_dir.join("state.vscdb")).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE ItemTable (key TEXT UNIQUE ON CONFLICT REPLACE, value BLOB);
+INSERT INTO ItemTable VALUES ('history.recentlyOpenedPathsList', '{\"entries\":[{\"folderUri\":\"file:///home/foo/mdcat\"},{\"fileUri\":\"file:///home/foo/.bashrc\"}]}');",
+            )
+            .unwrap();
+        drop(connection);
+
+        let storage = Storage::from_state_db(&config_dir).unwrap();
+        assert_eq!(
+            storage.into_workspaces(),
+            vec![
+                RecentWorkspace::new("file:///home/foo/mdcat".to_string()),
+                RecentWorkspace::file("file:///home/foo/.bashrc".to_string()),
+            ]
+        );
+        std::fs::remove_dir_all(&config_dir).unwrap();
+    }
+
+    #[test]
+    fn read_recent_workspaces_code_1_54() {
+        let data: &[u8] = include_bytes!("tests/code_1_54_storage.json");
+        let storage = Storage::read(data).unwrap();
+        assert!(
+            &storage.opened_paths_list.is_some(),
+            "opened paths list missing"
+        );
+        assert!(
+            &storage
+                .opened_paths_list
+                .as_ref()
+                .unwrap()
+                .workspaces3
+                .is_some(),
+            "workspaces3 missing"
+        );
+        assert_eq!(
+            urls(storage.into_workspaces()),
+            vec![
+                "file:///home/foo//mdcat",
+                "file:///home/foo//gnome-jetbrains-search-provider",
+                "file:///home/foo//gnome-shell",
+                "file:///home/foo//sbctl",
+            ]
+        )
+    }
+
+    #[test]
+    fn read_recent_workspaces_code_1_55() {
+        let data: &[u8] = include_bytes!("tests/code_1_55_storage.json");
+        let storage = Storage::read(data).unwrap();
+        assert!(
+            &storage.opened_paths_list.is_some(),
+            "opened paths list missing"
+        );
+        assert!(
+            &storage
+                .opened_paths_list
+                .as_ref()
+                .unwrap()
+                .entries
+                .is_some(),
+            "entries missing"
+        );
+
+        assert_eq!(
+            urls(storage.into_workspaces()),
+            vec![
+                "file:///home/foo//mdcat",
+                "file:///home/foo//gnome-jetbrains-search-provider",
+                "file:///home/foo//gnome-shell",
+                "file:///home/foo//sbctl",
+                "file:///tmp/foo",
+            ]
+        );
+    }
+
+    #[test]
+    fn read_recent_workspaces_code_workspace() {
+        let data: &[u8] = br#"{
+  "openedPathsList": {
+    "entries": [
+      {
+        "workspace": {
+          "id": "2b3c2d1e",
+          "configPath": "file:///home/foo/projects/foo.code-workspace"
+        }
+      },
+      {
+        "folderUri": "file:///home/foo/mdcat"
+      }
+    ]
+  }
+}"#;
+        assert_eq!(
+            urls(Storage::read(data).unwrap().into_workspaces()),
+            vec![
+                "file:///home/foo/projects/foo.code-workspace",
+                "file:///home/foo/mdcat",
+            ]
+        );
+    }
+
+    #[test]
+    fn read_recent_workspaces_with_label() {
+        let data: &[u8] = br#"{
+  "openedPathsList": {
+    "entries": [
+      {
+        "folderUri": "vscode-remote://ssh-remote%2Bfoo/home/foo/mdcat",
+        "label": "mdcat [SSH: foo]"
+      },
+      {
+        "folderUri": "file:///home/foo/sbctl"
+      }
+    ]
+  }
+}"#;
+        assert_eq!(
+            Storage::read(data).unwrap().into_workspaces(),
+            vec![
+                RecentWorkspace {
+                    label: Some("mdcat [SSH: foo]".to_string()),
+                    ..RecentWorkspace::new(
+                        "vscode-remote://ssh-remote%2Bfoo/home/foo/mdcat".to_string()
+                    )
+                },
+                RecentWorkspace::new("file:///home/foo/sbctl".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_recent_workspaces_skips_malformed_entries() {
+        let data: &[u8] = br#"{
+  "openedPathsList": {
+    "entries": [
+      { "folderUri": 42 },
+      { "folderUri": "file:///home/foo/mdcat" },
+      "garbage"
+    ],
+    "workspaces3": [
+      { "id": "2b3c2d1e" },
+      "file:///home/foo/sbctl"
+    ]
+  }
+}"#;
+        assert_eq!(
+            urls(Storage::read(data).unwrap().into_workspaces()),
+            vec!["file:///home/foo/mdcat", "file:///home/foo/sbctl"]
+        );
+    }
+
+    #[test]
+    fn read_recent_workspaces_at_new_key() {
+        let data: &[u8] = br#"{
+  "history.recentlyOpenedPathsList": {
+    "entries": [
+      { "folderUri": "file:///home/foo/mdcat" }
+    ]
+  }
+}"#;
+        assert_eq!(
+            urls(Storage::read(data).unwrap().into_workspaces()),
+            vec!["file:///home/foo/mdcat"]
+        );
+    }
+
+    #[test]
+    fn read_user_data_profiles() {
+        let data: &[u8] = br#"{
+  "userDataProfiles": [
+    {
+      "location": "-4a6b1c2d",
+      "name": "Work",
+      "icon": "briefcase"
+    }
+  ]
+}"#;
+        assert_eq!(
+            Storage::read(data).unwrap().user_data_profiles,
+            vec![UserDataProfile {
+                location: "-4a6b1c2d".to_string(),
+                name: "Work".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn read_backup_workspaces() {
+        let data: &[u8] = br#"{
+  "rootURIWorkspaces": [
+    {
+      "id": "2b3c2d1e",
+      "configURIPath": "file:///home/foo/projects/foo.code-workspace"
+    }
+  ],
+  "folderWorkspaceInfos": [
+    { "folderUri": "file:///home/foo/mdcat" }
+  ],
+  "emptyWindowBackupInfos": []
+}"#;
+        assert_eq!(
+            urls(BackupWorkspaces::read(data).unwrap().into_workspaces()),
+            vec![
+                "file:///home/foo/mdcat",
+                "file:///home/foo/projects/foo.code-workspace",
+            ]
+        );
+    }
+
+    #[test]
+    fn read_recent_workspaces_theia() {
+        let data: &[u8] = include_bytes!("tests/theia_recentworkspace.json");
+        let recent = TheiaRecentWorkspaces::read(data).unwrap();
+        assert_eq!(
+            recent.recent_roots,
+            vec!["file:///home/foo/mdcat", "file:///home/foo/gnome-shell"]
+        );
+    }
+}
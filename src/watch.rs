@@ -0,0 +1,59 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Watch storage of recent workspaces for changes.
+
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use log::{debug, trace};
+
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::prelude::*;
+
+/// Watch files and directories for changes.
+///
+/// Requires a running Glib main loop to notice changes.
+#[derive(Debug)]
+pub struct FileWatch {
+    /// Monitors of all watched paths, which stop watching when dropped.
+    _monitors: Vec<gio::FileMonitor>,
+    /// Whether any watched path changed.
+    changed: Rc<Cell<bool>>,
+}
+
+impl FileWatch {
+    /// Watch all `paths`, including paths which don't exist yet.
+    ///
+    /// Return `None` if any path can't be watched, e.g. because the system ran out of inotify
+    /// watches.
+    pub fn new(paths: &[PathBuf]) -> Option<Self> {
+        let changed = Rc::new(Cell::new(false));
+        let mut monitors = Vec::with_capacity(paths.len());
+        for path in paths {
+            let monitor = gio::File::for_path(path)
+                .monitor(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+                .map_err(|error| debug!("Failed to watch {}: {}", path.display(), error))
+                .ok()?;
+            let changed = changed.clone();
+            monitor.connect_changed(move |_, file, _, event| {
+                trace!("{:?} for {:?}", event, file.path());
+                changed.set(true);
+            });
+            monitors.push(monitor);
+        }
+        Some(Self {
+            _monitors: monitors,
+            changed,
+        })
+    }
+
+    /// Whether any watched path changed since this watch started.
+    pub fn has_changed(&self) -> bool {
+        self.changed.get()
+    }
+}